@@ -6,6 +6,12 @@ pub struct LMeta {
     pub end : usize,
 }
 
+impl Default for LMeta {
+    fn default() -> Self {
+        LMeta::new()
+    }
+}
+
 impl LMeta {
     pub fn new() -> Self { 
         LMeta { start: 0, end: 0 }
@@ -18,6 +24,156 @@ impl LMeta {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub line : usize,
+    pub column : usize,
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start : Location,
+    pub end : Location,
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+/// Resolves the byte offsets in `LMeta` into one based lines and columns, where columns count chars.
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    text : String,
+    line_starts : Vec<usize>,
+}
+
+impl SourceMap {
+    pub fn new(input : &str) -> Self {
+        let line_starts = std::iter::once(0)
+                            .chain(input.char_indices().filter(|(_, c)| *c == '\n').map(|(i, _)| i + 1))
+                            .collect();
+        SourceMap { text: input.to_string(), line_starts }
+    }
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+    /// Returns the given one based line without its line terminator.
+    pub fn line(&self, line : usize) -> Option<&str> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let end = self.line_starts.get(line).map(|e| e - 1).unwrap_or(self.text.len());
+        let ret = &self.text[start..end];
+        Some(ret.strip_suffix('\r').unwrap_or(ret))
+    }
+    pub fn location(&self, offset : usize) -> Location {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|s| *s <= offset);
+        let start = self.line_starts[line - 1];
+        let column = self.text.get(start..offset).map(|x| x.chars().count()).unwrap_or(offset - start) + 1;
+        Location { line, column }
+    }
+    pub fn span(&self, meta : &LMeta) -> Span {
+        Span { start: self.location(meta.start), end: self.location(meta.end) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NumberKind {
+    Integer,
+    Float,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumberLiteral {
+    pub radix : u32,
+    pub kind : NumberKind,
+    /// The digits of the literal without the radix prefix, digit separators or suffix.
+    pub digits : String,
+    pub suffix : Option<String>,
+}
+
+impl NumberLiteral {
+    /// Scans the longest numeric literal at the start of the input and returns its length in bytes.
+    pub fn scan(input : &str) -> Option<(usize, NumberLiteral)> {
+        fn is_digit(b : Option<&u8>, radix : u32) -> bool {
+            matches!(b, Some(b) if (*b as char).is_digit(radix))
+        }
+        fn take_digits(bs : &[u8], mut i : usize, radix : u32, digits : &mut String) -> usize {
+            while let Some(b) = bs.get(i) {
+                if (*b as char).is_digit(radix) {
+                    digits.push(*b as char);
+                }
+                else if *b != b'_' {
+                    break;
+                }
+                i += 1;
+            }
+            i
+        }
+
+        let bs = input.as_bytes();
+        if !is_digit(bs.first(), 10) {
+            return None;
+        }
+
+        let (radix, i) = match (bs[0], bs.get(1)) {
+            (b'0', Some(b'x' | b'X')) if is_digit(bs.get(2), 16) => (16, 2),
+            (b'0', Some(b'o' | b'O')) if is_digit(bs.get(2), 8) => (8, 2),
+            (b'0', Some(b'b' | b'B')) if is_digit(bs.get(2), 2) => (2, 2),
+            _ => (10, 0),
+        };
+
+        let mut kind = NumberKind::Integer;
+        let mut digits = String::new();
+        let mut i = take_digits(bs, i, radix, &mut digits);
+
+        if radix == 10 {
+            if bs.get(i) == Some(&b'.') && is_digit(bs.get(i + 1), 10) {
+                digits.push('.');
+                i = take_digits(bs, i + 1, 10, &mut digits);
+                kind = NumberKind::Float;
+            }
+            if matches!(bs.get(i), Some(b'e' | b'E')) {
+                let sign = matches!(bs.get(i + 1), Some(b'+' | b'-'));
+                let start = i + 1 + sign as usize;
+                if is_digit(bs.get(start), 10) {
+                    digits.push('e');
+                    if sign {
+                        digits.push(bs[i + 1] as char);
+                    }
+                    i = take_digits(bs, start, 10, &mut digits);
+                    kind = NumberKind::Float;
+                }
+            }
+        }
+
+        let suffix = match input[i..].chars().next() {
+            Some(c) if c.is_alphabetic() => {
+                let len = input[i..].chars()
+                                    .take_while(|c| c.is_alphanumeric() || *c == '_')
+                                    .map(|c| c.len_utf8())
+                                    .sum::<usize>();
+                let suffix = input[i..i + len].to_string();
+                i += len;
+                Some(suffix)
+            },
+            _ => None,
+        };
+
+        Some((i, NumberLiteral { radix, kind, digits, suffix }))
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Lexeme { 
     RParen(LMeta),
@@ -30,11 +186,18 @@ pub enum Lexeme {
     LSquare(LMeta),
 
     Punct(LMeta, char),
+    Operator(LMeta, String),
     Group(LMeta, String, Vec<Lexeme>),
 
-    String(LMeta, String),
+    /// The decoded value of a string and its source text with the delimiters and escapes.
+    String(LMeta, String, String),
     Number(LMeta, String),
     Symbol(LMeta, String),
+
+    /// Whitespace or a comment, only produced when the lexer is asked to keep trivia.
+    Trivia(LMeta, String),
+    /// Source text which could not be lexed.
+    Error(LMeta, String),
 }
 
 impl Lexeme {
@@ -50,17 +213,29 @@ impl Lexeme {
             (RSquare(_), RSquare(_)) => true,
             (LSquare(_), LSquare(_)) => true,
             (Punct(_, c1), Punct(_, c2)) if c1 == c2 => true,
+            (Operator(_, o1), Operator(_, o2)) if o1 == o2 => true,
             (Group(_, label1, g1), Group(_, label2, g2)) 
                 if 
                 label1 == label2 
                 && g1.iter().zip(g2.iter()).all(|(a, b)| a.lmatch(b)) => true,
 
-            (String(_, s1), String(_, s2)) if s1 == s2 => true,
+            (String(_, s1, _), String(_, s2, _)) if s1 == s2 => true,
             (Number(_, n1), Number(_, n2)) if n1 == n2 => true,
             (Symbol(_, s1), Symbol(_, s2)) if s1 == s2 => true,
+            (Trivia(_, t1), Trivia(_, t2)) if t1 == t2 => true,
+            (Error(_, e1), Error(_, e2)) if e1 == e2 => true,
             _ => false,
         }
     }
+    pub fn number_literal(&self) -> Option<NumberLiteral> {
+        match self {
+            Lexeme::Number(_, n) => match NumberLiteral::scan(n) {
+                Some((len, literal)) if len == n.len() => Some(literal),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
     pub fn meta(&self) -> LMeta {
         use Lexeme::*;
         match self {
@@ -73,12 +248,41 @@ impl Lexeme {
             RSquare(m) => m.clone(),
             LSquare(m) => m.clone(),
             Punct(m, _) => m.clone(),
+            Operator(m, _) => m.clone(),
             Group(m, _, _) => m.clone(),
-            String(m, _) => m.clone(),
+            String(m, _, _) => m.clone(),
             Number(m, _) => m.clone(),
             Symbol(m, _) => m.clone(),
+            Trivia(m, _) => m.clone(),
+            Error(m, _) => m.clone(),
         }
     }
+    /// The offset just after the last char of the lexeme.
+    pub fn end_offset(&self) -> usize {
+        fn after(m : &LMeta, text : &str) -> usize {
+            m.end + text.chars().last().map(|c| c.len_utf8()).unwrap_or(1)
+        }
+        match self {
+            Lexeme::Punct(m, c) => m.end + c.len_utf8(),
+            Lexeme::Operator(m, s) | Lexeme::Number(m, s) | Lexeme::Symbol(m, s) | Lexeme::Trivia(m, s) | Lexeme::Error(m, s) => after(m, s),
+            Lexeme::String(m, _, raw) => m.start + raw.len(),
+            Lexeme::Group(m, _, ls) => ls.last().map(|l| l.end_offset()).unwrap_or(m.end + 1),
+            l => l.meta().end + 1,
+        }
+    }
+    /// The source text of the lexeme without needing the input, which for strings includes the delimiters and
+    /// escapes.  Joining the `text` of a stream lexed with trivia reproduces the input.
+    pub fn text(&self) -> String {
+        match self {
+            Lexeme::String(_, _, raw) => raw.clone(),
+            Lexeme::Group(_, _, ls) => ls.iter().map(|l| l.text()).collect::<String>(),
+            l => l.value(),
+        }
+    }
+    /// The text of the input that the lexeme was lexed from, with string delimiters and escapes.
+    pub fn source<'a>(&self, input : &'a str) -> &'a str {
+        &input[self.meta().start..self.end_offset()]
+    }
     pub fn value(&self) -> String {
         match self {
             Lexeme::RParen(_) => ")".into(),
@@ -90,10 +294,13 @@ impl Lexeme {
             Lexeme:: RSquare(_) => "]".into(),
             Lexeme::LSquare(_) => "[".into(),
             Lexeme::Punct(_, c) => c.to_string(),
+            Lexeme::Operator(_, o) => o.clone(),
             Lexeme::Group(_, _, g) => g.iter().map(|x| x.value()).collect::<String>(),
-            Lexeme::String(_, s) => s.clone(),
+            Lexeme::String(_, s, _) => s.clone(),
             Lexeme::Number(_, n) => n.clone(),
             Lexeme::Symbol(_, sym) => sym.clone(),
+            Lexeme::Trivia(_, t) => t.clone(),
+            Lexeme::Error(_, e) => e.clone(),
         }
     }
 }
@@ -105,9 +312,12 @@ pub enum Bracket {
     Curl(LMeta, Vec<Bracket>),
     Square(LMeta, Vec<Bracket>),
     Lex(Lexeme),
+    /// A closing bracket that could not be matched with any opening bracket.
+    Error(Lexeme),
 }
 
 impl Bracket {
+    #[allow(clippy::map_all_any_identity)]
     pub fn lmatch(&self, other : &Bracket) -> bool {
         match (self, other) {
             (Bracket::Paren(_, xs), Bracket::Paren(_, ys)) => xs.iter().zip(ys.iter()).map(|(x, y)| x.lmatch(y)).all(|z| z),
@@ -115,6 +325,7 @@ impl Bracket {
             (Bracket::Curl(_, xs), Bracket::Curl(_, ys)) => xs.iter().zip(ys.iter()).map(|(x, y)| x.lmatch(y)).all(|z| z),
             (Bracket::Square(_, xs), Bracket::Square(_, ys)) => xs.iter().zip(ys.iter()).map(|(x, y)| x.lmatch(y)).all(|z| z),
             (Bracket::Lex(x), Bracket::Lex(y)) => x.lmatch(y),
+            (Bracket::Error(x), Bracket::Error(y)) => x.lmatch(y),
             _ => false,
         }
     }
@@ -126,6 +337,85 @@ impl Bracket {
             Curl(m, _) => m.clone(),
             Square(m, _) => m.clone(),
             Lex(l) => l.meta(),
+            Error(l) => l.meta(),
         }
     }
+    /// The offset just after the last char of the item.
+    pub fn end_offset(&self) -> usize {
+        match self {
+            Bracket::Lex(l) | Bracket::Error(l) => l.end_offset(),
+            x => x.meta().end + 1,
+        }
+    }
+    /// The text of the input that the item was parsed from, including any whitespace and comments inside it.
+    pub fn source<'a>(&self, input : &'a str) -> &'a str {
+        &input[self.meta().start..self.end_offset()]
+    }
+    /// The items between the brackets or `None` for items which are not brackets.
+    pub fn contents(&self) -> Option<&[Bracket]> {
+        match self {
+            Bracket::Paren(_, xs) | Bracket::Angle(_, xs) | Bracket::Curl(_, xs) | Bracket::Square(_, xs) => Some(xs),
+            Bracket::Lex(_) | Bracket::Error(_) => None,
+        }
+    }
+    /// Joins the `value` of every lexeme in the bracket.  Use `source` for the exact source text.
+    pub fn value(&self) -> String {
+        fn surround(open : char, xs : &[Bracket], close : char) -> String {
+            std::iter::once(open.to_string())
+                .chain(xs.iter().map(|x| x.value()))
+                .chain(std::iter::once(close.to_string()))
+                .collect::<String>()
+        }
+        match self {
+            Bracket::Paren(_, xs) => surround('(', xs, ')'),
+            Bracket::Angle(_, xs) => surround('<', xs, '>'),
+            Bracket::Curl(_, xs) => surround('{', xs, '}'),
+            Bracket::Square(_, xs) => surround('[', xs, ']'),
+            Bracket::Lex(l) => l.value(),
+            Bracket::Error(l) => l.value(),
+        }
+    }
+    /// Joins the `text` of every lexeme in the bracket, which is the source text when it was lexed with trivia.
+    pub fn text(&self) -> String {
+        fn surround(open : char, xs : &[Bracket], close : char) -> String {
+            std::iter::once(open.to_string())
+                .chain(xs.iter().map(|x| x.text()))
+                .chain(std::iter::once(close.to_string()))
+                .collect::<String>()
+        }
+        match self {
+            Bracket::Paren(_, xs) => surround('(', xs, ')'),
+            Bracket::Angle(_, xs) => surround('<', xs, '>'),
+            Bracket::Curl(_, xs) => surround('{', xs, '}'),
+            Bracket::Square(_, xs) => surround('[', xs, ']'),
+            Bracket::Lex(l) => l.text(),
+            Bracket::Error(l) => l.text(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn should_resolve_locations() {
+        let map = SourceMap::new("ab\r\ncd\n\nλx y");
+        assert_eq!(map.line_count(), 4);
+        assert_eq!(map.line(1), Some("ab"));
+        assert_eq!(map.line(2), Some("cd"));
+        assert_eq!(map.line(3), Some(""));
+        assert_eq!(map.line(4), Some("λx y"));
+        assert_eq!(map.line(0), None);
+        assert_eq!(map.line(5), None);
+
+        assert_eq!(map.location(0), Location { line: 1, column: 1 });
+        assert_eq!(map.location(4), Location { line: 2, column: 1 });
+        assert_eq!(map.location(5), Location { line: 2, column: 2 });
+        assert_eq!(map.location(7), Location { line: 3, column: 1 });
+        assert_eq!(map.location(10), Location { line: 4, column: 2 });
+        assert_eq!(map.location(12).to_string(), "4:4");
+        assert_eq!(map.span(&LMeta::multi(1, 5)).to_string(), "1:2-2:2");
+    }
 }
@@ -0,0 +1,358 @@
+
+use crate::data::{LMeta, SourceMap};
+use crate::parsing::lexer::LexError;
+use crate::parsing::bracketer::BracketError;
+use crate::matching::lexeme::FixpointError;
+use crate::matching::bracket::BracketProcessError;
+use crate::matching::expr::ExprError;
+use crate::matching::split::SplitError;
+use crate::matching::peg::PegError;
+use crate::matching::combinator::ParseError;
+use crate::matching::dsl::DslError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    Plain,
+    Ansi,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub meta : LMeta,
+    pub message : String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message : String,
+    /// `None` for errors which do not have a location in the source, which are rendered without a snippet.
+    pub primary : Option<Label>,
+    pub secondary : Vec<Label>,
+    pub notes : Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new<S : AsRef<str>>(message : S, meta : LMeta) -> Self {
+        Diagnostic {
+            message: message.as_ref().to_string(),
+            primary: Some(Label { meta, message: "".into() }),
+            secondary: vec![],
+            notes: vec![],
+        }
+    }
+    /// A diagnostic without a location, for errors which are not about any part of the source.
+    pub fn unlocated<S : AsRef<str>>(message : S) -> Self {
+        Diagnostic { message: message.as_ref().to_string(), primary: None, secondary: vec![], notes: vec![] }
+    }
+    pub fn label<S : AsRef<str>>(mut self, message : S) -> Self {
+        if let Some(primary) = &mut self.primary {
+            primary.message = message.as_ref().to_string();
+        }
+        self
+    }
+    pub fn secondary<S : AsRef<str>>(mut self, meta : LMeta, message : S) -> Self {
+        self.secondary.push(Label { meta, message: message.as_ref().to_string() });
+        self
+    }
+    pub fn note<S : AsRef<str>>(mut self, note : S) -> Self {
+        self.notes.push(note.as_ref().to_string());
+        self
+    }
+
+    pub fn render(&self, map : &SourceMap, style : Style) -> String {
+        let paint = |code : &str, text : &str| match style {
+            Style::Plain => text.to_string(),
+            Style::Ansi => format!("\x1b[{}m{}\x1b[0m", code, text),
+        };
+
+        let mut labels = match &self.primary {
+            Some(primary) => std::iter::once((true, primary)).chain(self.secondary.iter().map(|l| (false, l))).collect::<Vec<_>>(),
+            None => vec![],
+        };
+        labels.sort_by_key(|(_, l)| l.meta.start);
+
+        let width = labels.iter()
+                          .map(|(_, l)| map.location(l.meta.start).line.to_string().len())
+                          .max()
+                          .unwrap_or(1);
+        let gutter = |line : &str| paint("1;34", &format!("{:>width$} |", line, width = width));
+
+        let mut ret = vec![];
+        ret.push(format!("{}{}", paint("1;31", "error"), paint("1", &format!(": {}", self.message))));
+        if let Some(primary) = &self.primary {
+            ret.push(format!("{}{} {}", " ".repeat(width), paint("1;34", "-->"), map.location(primary.meta.start)));
+            ret.push(gutter(""));
+        }
+
+        let mut previous = None;
+        for (primary, label) in labels {
+            let span = map.span(&label.meta);
+            let text = map.line(span.start.line).unwrap_or("");
+
+            if previous != Some(span.start.line) {
+                ret.push(format!("{} {}", gutter(&span.start.line.to_string()), text));
+            }
+            previous = Some(span.start.line);
+
+            let line_len = text.chars().count();
+            let end = if span.end.line == span.start.line { span.end.column } else { line_len.max(span.start.column) };
+            let (mark, color) = if primary { ('^', "1;31") } else { ('-', "1;34") };
+            let marks = std::iter::repeat_n(mark, (end + 1).saturating_sub(span.start.column).max(1)).collect::<String>();
+            let underline = if label.message.is_empty() { marks } else { format!("{} {}", marks, label.message) };
+
+            ret.push(format!("{} {}{}", gutter(""), " ".repeat(span.start.column - 1), paint(color, &underline)));
+        }
+
+        for note in &self.notes {
+            ret.push(format!("{} {} {}", " ".repeat(width), paint("1;34", "="), paint("1", &format!("note: {}", note))));
+        }
+
+        ret.join("\n")
+    }
+}
+
+pub trait ToDiagnostic {
+    fn to_diagnostic(&self) -> Diagnostic;
+}
+
+impl ToDiagnostic for LexError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            LexError::EncounteredEndInString(_) =>
+                Diagnostic::new("unterminated string", self.meta())
+                    .label("string starts here")
+                    .note("the end of the file was reached before the closing delimiter"),
+            LexError::UnexpectedEscapeInString(_, c) =>
+                Diagnostic::new(format!("unexpected escape `\\{}` in string", c), self.meta())
+                    .label("unknown escape"),
+        }
+    }
+}
+
+impl ToDiagnostic for BracketError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            BracketError::MissingEndBracket { initial, found, expected, .. } =>
+                Diagnostic::new("mismatched closing bracket", self.meta())
+                    .label(format!("expected `{}`, but found `{}`", expected, found))
+                    .secondary(LMeta::single(*initial), "opening bracket"),
+            BracketError::EofInsteadOfEndBracket { expected, .. } =>
+                Diagnostic::new("unclosed bracket", self.meta())
+                    .label("opening bracket")
+                    .note(format!("the end of the file was reached before `{}`", expected)),
+            BracketError::NotAllInputConsumed(_) =>
+                Diagnostic::new("unexpected closing bracket", self.meta())
+                    .label("no matching opening bracket"),
+        }
+    }
+}
+
+impl ToDiagnostic for BracketProcessError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        if let Some(expected) = self.expected() {
+            let matched = self.best_matches()[0].matched;
+            let buffer = self.buffer();
+            let label = if matched < buffer.len() { "unexpected item" } else { "input ends here" };
+            let diagnostic = Diagnostic::new(expected, self.expected_meta()).label(label);
+            if matched == 0 {
+                return diagnostic;
+            }
+            let start = buffer[0].meta().start;
+            let end = buffer[matched - 1].meta().end;
+            return diagnostic.secondary(LMeta::multi(start, end), "partially matches a rule");
+        }
+        match self {
+            BracketProcessError::CurrentBufferExceedsAllPatterns(_, _) =>
+                Diagnostic::new("no rule matches this input", self.meta())
+                    .label("longer than every rule"),
+            BracketProcessError::CurrentBufferCannotBeMatchedAgainstAnyPattern(_, _) =>
+                Diagnostic::new("no rule matches this input", self.meta())
+                    .label("unmatched input"),
+            BracketProcessError::NoMatch(_, _) =>
+                Diagnostic::new("no rule matches this input", self.meta())
+                    .label("unmatched input"),
+            BracketProcessError::UnknownRuleSet(name) =>
+                Diagnostic::unlocated(format!("unknown rule set `{}`", name))
+                    .note("the grammar does not have a rule set with this name"),
+            BracketProcessError::AmbiguousMatch(_, rules) =>
+                Diagnostic::new("ambiguous input", self.meta())
+                    .label(format!("matched by rules {}", rules.iter().map(|r| r.to_string()).collect::<Vec<_>>().join(", ")))
+                    .note("give one of the rules a higher priority"),
+        }
+    }
+}
+
+impl ToDiagnostic for FixpointError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            FixpointError::IterationLimit(_, limit) =>
+                Diagnostic::new(format!("rules did not settle after {} passes", limit), self.meta())
+                    .label("still changing"),
+        }
+    }
+}
+
+impl ToDiagnostic for ExprError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            ExprError::UnexpectedEnd(_) =>
+                Diagnostic::new("expected an operand", self.meta())
+                    .label("expression ends here"),
+            ExprError::UnexpectedItem(_) =>
+                Diagnostic::new("unexpected item in expression", self.meta())
+                    .label("not an operand or operator"),
+        }
+    }
+}
+
+impl ToDiagnostic for SplitError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            SplitError::EmptySegment(_) =>
+                Diagnostic::new("empty segment", self.meta())
+                    .label("separator without anything before it"),
+            SplitError::TrailingSeparator(_) =>
+                Diagnostic::new("trailing separator", self.meta())
+                    .label("separator after the last segment"),
+            SplitError::MissingTrailingSeparator(_) =>
+                Diagnostic::new("missing trailing separator", self.meta())
+                    .label("expected a separator after this"),
+        }
+    }
+}
+
+impl ToDiagnostic for PegError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            PegError::UnknownRule(name) =>
+                Diagnostic::unlocated(format!("unknown rule `{}`", name))
+                    .note("the grammar does not have a rule with this name"),
+            PegError::Expected(_, expected) if expected.is_empty() =>
+                Diagnostic::new("no rule matches this input", self.meta()),
+            PegError::Expected(_, expected) =>
+                Diagnostic::new(format!("expected {}", expected.join(" or ")), self.meta())
+                    .label("unexpected item"),
+        }
+    }
+}
+
+impl ToDiagnostic for ParseError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        if self.expected.is_empty() {
+            return Diagnostic::new("unexpected input", self.meta.clone());
+        }
+        Diagnostic::new(format!("expected {}", self.expected.join(" or ")), self.meta.clone())
+            .label("unexpected item")
+    }
+}
+
+impl ToDiagnostic for DslError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        match self {
+            DslError::Lex(e) => e.to_diagnostic(),
+            DslError::Bracket(e) => e.to_diagnostic(),
+            DslError::MissingPlaceholderName(_) =>
+                Diagnostic::new("missing placeholder name", self.meta())
+                    .label("expected a name after `$`")
+                    .note("use `$$` to match a `$`"),
+            DslError::UnknownPlaceholder(_, name) =>
+                Diagnostic::new(format!("unknown placeholder `${}`", name), self.meta())
+                    .label("not bound to a predicate"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parsing::{lexer, bracketer};
+
+    #[test]
+    fn should_render_bracket_error() {
+        let input = "( 1 2\n ]";
+        let tokens = lexer::lex(input).unwrap().into_iter();
+        let error = bracketer::bracket(tokens).unwrap_err();
+        let output = error.to_diagnostic().render(&SourceMap::new(input), Style::Plain);
+        let expected = "\
+error: mismatched closing bracket
+ --> 2:2
+  |
+1 | ( 1 2
+  | - opening bracket
+2 |  ]
+  |  ^ expected `)`, but found `]`";
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn should_render_span_and_notes() {
+        let input = "let x = \"abc";
+        let error = lexer::lex(input).unwrap_err();
+        let output = error.to_diagnostic().render(&SourceMap::new(input), Style::Plain);
+        let expected = "\
+error: unterminated string
+ --> 1:9
+  |
+1 | let x = \"abc
+  |         ^ string starts here
+  = note: the end of the file was reached before the closing delimiter";
+        assert_eq!(output, expected);
+
+        let output = Diagnostic::new("message", LMeta::multi(4, 8)).render(&SourceMap::new(input), Style::Plain);
+        assert!(output.ends_with("1 | let x = \"abc\n  |     ^^^^^"));
+    }
+
+    #[test]
+    fn should_render_expected_items() {
+        use crate::data::{Bracket, Lexeme};
+        use crate::matching::bracket::{self, Pattern, Rule};
+
+        let input = "if (a)\n  b";
+        let brackets = bracketer::bracket(lexer::lex(input).unwrap().into_iter()).unwrap();
+        let rules = vec![ Rule::new( vec![ Pattern::Exact(Bracket::Lex(Lexeme::Symbol(LMeta::new(), "if".into())))
+                                         , Pattern::Wild
+                                         , Pattern::Expect("`{`".into(), Box::new(Pattern::Pred(|x| matches!(x, Bracket::Curl(_, _)))))
+                                         ]
+                                   , |_| ())
+                        ];
+        let error = bracket::process(&rules, brackets.into_iter()).unwrap_err();
+        let output = error.to_diagnostic().render(&SourceMap::new(input), Style::Plain);
+        let expected = "\
+error: expected `{` after `if (...)`
+ --> 2:3
+  |
+1 | if (a)
+  | ------ partially matches a rule
+2 |   b
+  |   ^ unexpected item";
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn should_render_unlocated_errors_without_a_snippet() {
+        use crate::matching::bracket::Grammar;
+        use crate::matching::peg;
+
+        let map = SourceMap::new("a b\nc");
+        let error = Grammar::<()>::new().process("missing", vec![].into_iter()).unwrap_err();
+        let expected = "\
+error: unknown rule set `missing`
+  = note: the grammar does not have a rule set with this name";
+        assert_eq!(error.to_diagnostic().render(&map, Style::Plain), expected);
+
+        let error = peg::PegError::UnknownRule("missing".into());
+        let expected = "\
+error: unknown rule `missing`
+  = note: the grammar does not have a rule with this name";
+        assert_eq!(error.to_diagnostic().render(&map, Style::Plain), expected);
+    }
+
+    #[test]
+    fn should_render_ansi() {
+        let input = "\"\\q\"";
+        let error = lexer::lex(input).unwrap_err();
+        let output = error.to_diagnostic().render(&SourceMap::new(input), Style::Ansi);
+        assert!(output.starts_with("\x1b[1;31merror\x1b[0m\x1b[1m: unexpected escape `\\q` in string\x1b[0m"));
+        assert!(output.ends_with("\x1b[1;31m^ unknown escape\x1b[0m"));
+    }
+}
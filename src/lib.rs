@@ -1,4 +1,6 @@
 
 pub mod data;
 pub mod parsing;
-pub mod matching;
\ No newline at end of file
+pub mod matching;
+pub mod diagnostics;
+mod macros;
\ No newline at end of file
@@ -0,0 +1,225 @@
+/// Expands to an array of `matching::lexeme::Pattern`.
+///
+/// Pattern items are written as:
+///
+/// * `_` for any item
+/// * `num`, `sym`, `str` or `punct` for any number, symbol, string or punctuation
+/// * a literal like `"."`, `'.'`, `"if"` or `1` for the lexemes it lexes as with the default lexer, see `matching::lexeme::literal_pattern`
+/// * `sym!(if)` for an exact symbol
+/// * `cap!(name, items...)` to capture the items as `name`
+/// * `alt!([items...] [items...])` for alternatives
+/// * `adj!()` and `gap!()` between items that must be glued together or spaced apart
+/// * `group!(label)` for a group with the label in lexeme patterns
+/// * `opt!(items...)`, `many!(items...)` and `many1!(items...)` in lexeme patterns
+/// * `paren!(_)`, `angle!(_)`, `curl!(_)` and `square!(_)` for any bracket of that kind in bracket patterns,
+///   or `paren!(items...)` for a bracket whose contents all match the items
+///
+/// Malformed items are compile errors, like an unknown item
+///
+/// ```compile_fail
+/// let _ = yoke::lex_pat![num foo];
+/// ```
+///
+/// a capture without items
+///
+/// ```compile_fail
+/// let _ = yoke::lex_pat![cap!(name)];
+/// ```
+///
+/// alternatives which are not in square brackets
+///
+/// ```compile_fail
+/// let _ = yoke::lex_pat![alt!(num)];
+/// ```
+///
+/// a repetition without items
+///
+/// ```compile_fail
+/// let _ = yoke::lex_pat![opt!()];
+/// ```
+///
+/// or a group label or exact symbol which is not an identifier.
+///
+/// ```compile_fail
+/// let _ = yoke::lex_pat![group!("x")];
+/// ```
+///
+/// ```compile_fail
+/// let _ = yoke::lex_pat![sym!(1)];
+/// ```
+#[macro_export]
+macro_rules! lex_pat {
+    ($($items:tt)*) => { $crate::__items!(__lex_item [] $($items)*) };
+}
+
+/// Expands to a `Vec` of `matching::bracket::Pattern`, with items as for `lex_pat!`.  Items which are only
+/// for lexeme patterns are compile errors, as are malformed items inside brackets.
+///
+/// ```compile_fail
+/// let _ = yoke::bracket_pat![opt!(num)];
+/// ```
+///
+/// ```compile_fail
+/// let _ = yoke::bracket_pat![group!(x)];
+/// ```
+///
+/// ```compile_fail
+/// let _ = yoke::bracket_pat![paren!(foo)];
+/// ```
+#[macro_export]
+macro_rules! bracket_pat {
+    ($($items:tt)*) => { Vec::from($crate::__items!(__bracket_item [] $($items)*)) };
+}
+
+/// Expands to a `matching::bracket::Rule` from pattern items followed by `=>` and an action.  A rule
+/// without the action is a compile error.
+///
+/// ```compile_fail
+/// let _ = yoke::bracket_rule![sym num];
+/// ```
+#[macro_export]
+macro_rules! bracket_rule {
+    (@munch [$($items:tt)*] => $f:expr) => { $crate::matching::bracket::Rule::new($crate::bracket_pat![$($items)*], $f) };
+    (@munch [$($items:tt)*] $next:tt $($rest:tt)*) => { $crate::bracket_rule!(@munch [$($items)* $next] $($rest)*) };
+    (@munch [$($items:tt)*]) => { compile_error!("expected `=>` followed by an action after the bracket rule pattern") };
+    ($($tokens:tt)*) => { $crate::bracket_rule!(@munch [] $($tokens)*) };
+}
+
+/// Splits pattern items and expands each one with the item macro.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __items {
+    ($item:ident [$($out:expr),*]) => { [$($out),*] };
+    ($item:ident [$($out:expr),*] $name:ident ! ($($inner:tt)*) $($rest:tt)*) => {
+        $crate::__items!($item [$($out,)* $crate::$item!($name!($($inner)*))] $($rest)*)
+    };
+    ($item:ident [$($out:expr),*] $next:tt $($rest:tt)*) => {
+        $crate::__items!($item [$($out,)* $crate::$item!($next)] $($rest)*)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __lex_item {
+    (_) => { $crate::matching::lexeme::Pattern::Wild };
+    (num) => { $crate::matching::lexeme::Pattern::Pred(|x| matches!(x, $crate::data::Lexeme::Number(_, _))) };
+    (sym) => { $crate::matching::lexeme::Pattern::Pred(|x| matches!(x, $crate::data::Lexeme::Symbol(_, _))) };
+    (str) => { $crate::matching::lexeme::Pattern::Pred(|x| matches!(x, $crate::data::Lexeme::String(_, _, _))) };
+    (punct) => { $crate::matching::lexeme::Pattern::Pred(|x| matches!(x, $crate::data::Lexeme::Punct(_, _))) };
+    (sym!($s:ident)) => { $crate::matching::lexeme::Pattern::Exact($crate::matching::lexeme::symbol(stringify!($s))) };
+    (cap!($name:ident, $($items:tt)+)) => {
+        $crate::matching::lexeme::Pattern::Capture(stringify!($name).to_string(), Box::new($crate::__lex_item!(seq!($($items)+))))
+    };
+    (alt!($([$($items:tt)+])+)) => {
+        $crate::matching::lexeme::Pattern::Alt(vec![$($crate::__lex_item!(seq!($($items)+))),+])
+    };
+    (opt!($($items:tt)+)) => { $crate::matching::lexeme::Pattern::Optional(Box::new($crate::__lex_item!(seq!($($items)+)))) };
+    (many!($($items:tt)+)) => { $crate::matching::lexeme::Pattern::ZeroOrMore(Box::new($crate::__lex_item!(seq!($($items)+)))) };
+    (many1!($($items:tt)+)) => { $crate::matching::lexeme::Pattern::OneOrMore(Box::new($crate::__lex_item!(seq!($($items)+)))) };
+    (group!($label:ident)) => { $crate::matching::lexeme::Pattern::Label(stringify!($label).to_string()) };
+    (adj!()) => { $crate::matching::lexeme::Pattern::Adjacent };
+    (gap!()) => { $crate::matching::lexeme::Pattern::Separated };
+    (seq!($($items:tt)+)) => { $crate::matching::lexeme::Pattern::Seq(Vec::from($crate::lex_pat![$($items)+])) };
+    ($lit:literal) => { $crate::matching::lexeme::literal_pattern($lit.to_string()) };
+    ($($other:tt)*) => { compile_error!(concat!("malformed lexeme pattern item: ", stringify!($($other)*))) };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __bracket_item {
+    (_) => { $crate::matching::bracket::Pattern::Wild };
+    (num) => { $crate::__bracket_item!(@lex Number) };
+    (sym) => { $crate::__bracket_item!(@lex Symbol) };
+    (str) => { $crate::__bracket_item!(@lex String) };
+    (punct) => { $crate::__bracket_item!(@lex Punct) };
+    (@lex $kind:ident) => {
+        $crate::matching::bracket::Pattern::Pred(|x| matches!(x, $crate::data::Bracket::Lex($crate::data::Lexeme::$kind(..))))
+    };
+    (sym!($s:ident)) => {
+        $crate::matching::bracket::Pattern::Exact($crate::data::Bracket::Lex($crate::matching::lexeme::symbol(stringify!($s))))
+    };
+    (cap!($name:ident, $($items:tt)+)) => {
+        $crate::matching::bracket::Pattern::Capture(stringify!($name).to_string(), Box::new($crate::__bracket_item!(seq!($($items)+))))
+    };
+    (alt!($([$($items:tt)+])+)) => {
+        $crate::matching::bracket::Pattern::Alt(vec![$($crate::__bracket_item!(seq!($($items)+))),+])
+    };
+    (adj!()) => { $crate::matching::bracket::Pattern::Adjacent };
+    (gap!()) => { $crate::matching::bracket::Pattern::Separated };
+    (seq!($($items:tt)+)) => { $crate::matching::bracket::Pattern::Seq($crate::bracket_pat![$($items)+]) };
+    (paren!(_)) => { $crate::matching::bracket::Pattern::Pred(|x| matches!(x, $crate::data::Bracket::Paren(_, _))) };
+    (angle!(_)) => { $crate::matching::bracket::Pattern::Pred(|x| matches!(x, $crate::data::Bracket::Angle(_, _))) };
+    (curl!(_)) => { $crate::matching::bracket::Pattern::Pred(|x| matches!(x, $crate::data::Bracket::Curl(_, _))) };
+    (square!(_)) => { $crate::matching::bracket::Pattern::Pred(|x| matches!(x, $crate::data::Bracket::Square(_, _))) };
+    (paren!($($items:tt)*)) => { $crate::matching::bracket::Pattern::paren($crate::bracket_pat![$($items)*]) };
+    (angle!($($items:tt)*)) => { $crate::matching::bracket::Pattern::angle($crate::bracket_pat![$($items)*]) };
+    (curl!($($items:tt)*)) => { $crate::matching::bracket::Pattern::curl($crate::bracket_pat![$($items)*]) };
+    (square!($($items:tt)*)) => { $crate::matching::bracket::Pattern::square($crate::bracket_pat![$($items)*]) };
+    ($lit:literal) => { $crate::matching::bracket::Pattern::literal($lit.to_string()) };
+    ($($other:tt)*) => { compile_error!(concat!("malformed bracket pattern item: ", stringify!($($other)*))) };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data::*;
+    use crate::parsing::{lexer, bracketer};
+    use crate::matching::{lexeme, bracket};
+
+    #[test]
+    fn should_expand_lexeme_patterns() {
+        let input = lexer::lex("1 . 2 x = 3 . 4 y").unwrap();
+        let output = lexeme::grouper(lex_pat![num "." num], "float", input.into_iter()).collect::<Vec<_>>();
+        assert_eq!(output.len(), 5);
+        assert!(matches!(&output[0], Lexeme::Group(_, label, ls) if label == "float" && ls.len() == 3));
+        assert!(matches!(&output[3], Lexeme::Group(_, label, ls) if label == "float" && ls.len() == 3));
+
+        let input = lexer::lex("1.2 x = 3 . 4").unwrap();
+        let output = lexeme::grouper(lex_pat![num adj!() "." adj!() num], "float", input.into_iter()).collect::<Vec<_>>();
+        assert_eq!(output.len(), 6);
+        assert!(matches!(&output[0], Lexeme::Group(_, label, ls) if label == "float" && ls.len() == 3));
+
+        let input = lexer::lex("x = 1.2").unwrap();
+        let output = lexeme::grouper(lex_pat![num "." num], "float", input.into_iter());
+        let output = lexeme::grouper(lex_pat![sym "=" group!(float)], "assign", output).collect::<Vec<_>>();
+        assert!(matches!(&output[..], [Lexeme::Group(_, label, ls)] if label == "assign" && ls.len() == 3));
+
+        let input = lexer::lex("a.b.c = 1").unwrap();
+        let pattern = lex_pat![cap!(path, sym many!('.' sym)) opt!(alt!(["="] [":" "="])) cap!(value, _)];
+        let output = lexeme::process_match(pattern, |m| {
+            assert_eq!(m.get("path").unwrap().len(), 5);
+            vec![m.single("value").unwrap().clone()]
+        }, input.into_iter()).flatten().collect::<Vec<_>>();
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].value(), "1");
+    }
+
+    #[test]
+    fn should_expand_literals_to_the_default_lexemes() {
+        let input = lexer::lex("x == 1 y = = 2 1.5 1 . 5").unwrap();
+        let output = lexeme::grouper(lex_pat![sym "==" num], "eq", input.into_iter());
+        let output = lexeme::grouper(lex_pat!["1.5"], "float", output).collect::<Vec<_>>();
+        assert_eq!(output.len(), 9);
+        assert!(matches!(&output[0], Lexeme::Group(_, label, ls) if label == "eq" && ls.len() == 4));
+        assert!(matches!(&output[5], Lexeme::Group(_, label, ls) if label == "float" && ls.len() == 3));
+
+        let rules = vec![bracket_rule![sym "==" num => |xs : Vec<Bracket>| xs[0].value()]];
+        let input = bracketer::bracket(lexer::lex("x == 1").unwrap().into_iter()).unwrap();
+        assert_eq!(bracket::process(&rules, input.into_iter()).unwrap(), vec!["x"]);
+        let input = bracketer::bracket(lexer::lex("x = = 1").unwrap().into_iter()).unwrap();
+        assert!(bracket::process(&rules, input.into_iter()).is_err());
+    }
+
+    #[test]
+    fn should_expand_bracket_rules() {
+        let rules = vec![ bracket_rule![sym!(if) paren!(_) curl!(_) => |_| "if".to_string()]
+                        , bracket_rule![sym!(while) paren!(sym) curl!() => |_| "while".to_string()]
+                        , bracket_rule![cap!(name, sym) "=" alt!([num] [str]) ';' => |xs : Vec<Bracket>| xs[0].value()]
+                        ];
+        let input = bracketer::bracket(lexer::lex("if (a, b) { c } while (d) {} x = 1; y = \"z\";").unwrap().into_iter()).unwrap();
+        let output = bracket::process(&rules, input.into_iter()).unwrap();
+        assert_eq!(output, vec!["if", "while", "x", "y"]);
+
+        let input = bracketer::bracket(lexer::lex("while (1) {}").unwrap().into_iter()).unwrap();
+        assert!(bracket::process(&rules, input.into_iter()).is_err());
+    }
+}
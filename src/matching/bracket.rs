@@ -1,38 +1,367 @@
 
-use crate::data::Bracket;
+use std::collections::VecDeque;
+use crate::data::{Bracket, LMeta};
+use super::{Match, Found, Predicate};
 
 #[derive(Debug)]
 pub enum Pattern {
     Wild,
     Exact(Bracket),
     Pred(fn(&Bracket) -> bool),
+    Closure(Predicate<Bracket>),
+    Seq(Vec<Pattern>),
+    /// Matches any one of the patterns.  Use `Rule::with_match` to find out which one matched.  Under
+    /// `Strategy::First` the rule is applied as soon as the buffer matches, so the earliest alternative that
+    /// matches wins even when a later one would match more items: `Alt([Wild, Seq([Wild, Wild])])` splits
+    /// `a b c` into three matches.  `Strategy::Longest` and `lexeme::Pattern::Alt` split it into two and one.
+    Alt(Vec<Pattern>),
+    /// Names the items matched by the inner pattern so that they can be retrieved from the `Match`.
+    Capture(String, Box<Pattern>),
+    /// Matches one item with the inner pattern and then processes the contents of that item with the 
+    /// named rule set of the `Grammar`.  Items which are not brackets are processed as themselves.  The
+    /// contents are only processed for rules made with `Rule::with_nested`.
+    Parse(String, Box<Pattern>),
+    /// Describes the inner pattern as a whole in the expected items of a `BracketProcessError`.
+    Expect(String, Box<Pattern>),
+    /// Matches no items when the next item starts right where the previous item ends.  Neither this
+    /// nor `Separated` matches at the start of the data.
+    Adjacent,
+    /// Matches no items when there is a gap, like whitespace or a comment, before the next item.
+    Separated,
 }
 
-fn pattern_match(pattern : &[Pattern], data : &[Bracket]) -> bool {
-    let pds = pattern.iter().zip(data.iter());
-    for pd in pds {
-        match pd {
-            (Pattern::Wild, _) => { },
-            (Pattern::Pred(f), d) if f(d) => { },
-            (Pattern::Exact(l), d) if l.lmatch(d) => { },
-            _ => { return false; },
+impl Pattern {
+    /// Whether the pattern matches exactly the one given item.
+    pub fn matches_item(&self, item : &Bracket) -> bool {
+        pattern_match(std::slice::from_ref(self), std::slice::from_ref(item)).0.is_some()
+    }
+    /// Matches a paren whose contents all match the pattern.
+    pub fn paren(pattern : Vec<Pattern>) -> Pattern {
+        contents(pattern, |x| match x { Bracket::Paren(_, xs) => Some(xs), _ => None })
+    }
+    pub fn angle(pattern : Vec<Pattern>) -> Pattern {
+        contents(pattern, |x| match x { Bracket::Angle(_, xs) => Some(xs), _ => None })
+    }
+    pub fn curl(pattern : Vec<Pattern>) -> Pattern {
+        contents(pattern, |x| match x { Bracket::Curl(_, xs) => Some(xs), _ => None })
+    }
+    pub fn square(pattern : Vec<Pattern>) -> Pattern {
+        contents(pattern, |x| match x { Bracket::Square(_, xs) => Some(xs), _ => None })
+    }
+    /// Matches the lexemes of `lexeme::literal` with the pattern from `lexeme::literal_pattern`.
+    pub fn literal<S : AsRef<str>>(s : S) -> Pattern {
+        fn convert(pattern : super::lexeme::Pattern) -> Pattern {
+            use super::lexeme::Pattern as L;
+            match pattern {
+                L::Exact(l) => Pattern::Exact(Bracket::Lex(l)),
+                L::Adjacent => Pattern::Adjacent,
+                L::Separated => Pattern::Separated,
+                L::Seq(ps) => Pattern::Seq(ps.into_iter().map(convert).collect()),
+                p => unreachable!("literal patterns are made of exact lexemes and spacing but found {:?}", p),
+            }
+        }
+        convert(super::lexeme::literal_pattern(s))
+    }
+    /// A short description of what the pattern matches for error messages.
+    pub fn describe(&self) -> String {
+        match self {
+            Pattern::Wild => "any item".into(),
+            Pattern::Exact(x) => format!("`{}`", brief(x)),
+            Pattern::Pred(_) | Pattern::Closure(_) => "an item matching a predicate".into(),
+            Pattern::Seq(ps) if ps.is_empty() => "nothing".into(),
+            Pattern::Seq(ps) => ps.iter().map(|p| p.describe()).collect::<Vec<_>>().join(" "),
+            Pattern::Alt(ps) => ps.iter().map(|p| p.describe()).collect::<Vec<_>>().join(" or "),
+            Pattern::Capture(_, p) => p.describe(),
+            Pattern::Parse(_, p) => p.describe(),
+            Pattern::Expect(name, _) => name.clone(),
+            Pattern::Adjacent => "no space".into(),
+            Pattern::Separated => "a space".into(),
+        }
+    }
+}
+
+fn contents(pattern : Vec<Pattern>, select : fn(&Bracket) -> Option<&Vec<Bracket>>) -> Pattern {
+    Pattern::Closure(Predicate::new(move |x| match select(x) {
+        Some(xs) => full_match(&pattern, xs),
+        None => false,
+    }))
+}
+
+/// Abbreviates the contents of brackets.
+fn brief(x : &Bracket) -> String {
+    fn surround(open : char, xs : &[Bracket], close : char) -> String {
+        if xs.is_empty() { format!("{}{}", open, close) } else { format!("{}...{}", open, close) }
+    }
+    match x {
+        Bracket::Paren(_, xs) => surround('(', xs, ')'),
+        Bracket::Angle(_, xs) => surround('<', xs, '>'),
+        Bracket::Curl(_, xs) => surround('{', xs, '}'),
+        Bracket::Square(_, xs) => surround('[', xs, ']'),
+        Bracket::Lex(l) => l.value(),
+        Bracket::Error(l) => l.value(),
+    }
+}
+
+/// The most items that the pattern can match.
+fn max_len(pattern : &Pattern) -> usize {
+    match pattern {
+        Pattern::Seq(ps) => ps.iter().map(max_len).sum(),
+        Pattern::Alt(ps) => ps.iter().map(max_len).max().unwrap_or(0),
+        Pattern::Capture(_, p) | Pattern::Expect(_, p) => max_len(p),
+        Pattern::Adjacent | Pattern::Separated => 0,
+        _ => 1,
+    }
+}
+
+fn single_match(pattern : &Pattern, data : &Bracket) -> bool {
+    match pattern {
+        Pattern::Wild => true,
+        Pattern::Pred(f) => f(data),
+        Pattern::Closure(f) => f.test(data),
+        Pattern::Exact(l) => l.lmatch(data),
+        _ => false,
+    }
+}
+
+/// The index of each item matched by a `Parse` pattern along with the name of its rule set.
+type Parses = Vec<(usize, String)>;
+
+/// Returns the `Alt` branches, captures and `Parse` items taken if the pattern matches all of the data and 
+/// whether or not the pattern could continue matching if there was more data.
+fn pattern_match(pattern : &[Pattern], data : &[Bracket]) -> (Option<(Found, Parses)>, bool) {
+    let mut ret = None;
+    let mut matcher = Matcher::new(data, false);
+    matcher.seq(pattern, 0, &mut |m, end| 
+        if end == m.data.len() && ret.is_none() {
+            let found = Found { end, branches: m.branches.clone(), captures: m.captures.clone() };
+            ret = Some((found, m.parses.clone()));
+        });
+    (ret, matcher.incomplete)
+}
+
+/// Matches the pattern against the start of the data and takes the first way that it matches, which makes
+/// `Alt` an ordered choice.  Otherwise returns how far it got and the descriptions of what was expected there.
+pub(crate) fn prefix_match(pattern : &Pattern, data : &[Bracket]) -> Result<Found, (usize, Vec<String>)> {
+    let mut ret = None;
+    let mut matcher = Matcher::new(data, false);
+    matcher.one(pattern, 0, &mut |m, end|
+        if ret.is_none() {
+            ret = Some(Found { end, branches: m.branches.clone(), captures: m.captures.clone() });
+        });
+    if let Some(found) = ret {
+        return Ok(found);
+    }
+    let mut matcher = Matcher::new(data, true);
+    matcher.one(pattern, 0, &mut |_, _| { });
+    Err((matcher.furthest, matcher.expected))
+}
+
+/// Whether the pattern matches all of the data.
+pub(crate) fn full_match(pattern : &[Pattern], data : &[Bracket]) -> bool {
+    pattern_match(pattern, data).0.is_some()
+}
+
+/// How far a rule got into a buffer that no rule matched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartialMatch {
+    /// The index of the rule in its rule set.
+    pub rule : usize,
+    /// The number of items at the start of the buffer that the rule matched.
+    pub matched : usize,
+    /// Descriptions of the patterns which could have matched the next item.
+    pub expected : Vec<String>,
+}
+
+fn partial_matches<T>(rules : &[Rule<'_, T>], data : &[Bracket]) -> Vec<PartialMatch> {
+    rules.iter().enumerate().map(|(rule, r)| {
+        let mut matcher = Matcher::new(data, true);
+        matcher.seq(&r.pattern, 0, &mut |_, _| { });
+        PartialMatch { rule, matched: matcher.furthest, expected: matcher.expected }
+    }).collect()
+}
+
+struct Matcher<'a> {
+    data : &'a [Bracket],
+    incomplete : bool,
+    branches : Vec<usize>,
+    captures : Vec<(String, std::ops::Range<usize>)>,
+    parses : Parses,
+    track : bool,
+    furthest : usize,
+    expected : Vec<String>,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(data : &'a [Bracket], track : bool) -> Self {
+        Matcher { data, incomplete: false, branches: vec![], captures: vec![], parses: vec![], track, furthest: 0, expected: vec![] }
+    }
+
+    fn expect(&mut self, index : usize, description : String) {
+        if index > self.furthest {
+            self.furthest = index;
+            self.expected.clear();
+        }
+        if index == self.furthest && !self.expected.contains(&description) {
+            self.expected.push(description);
+        }
+    }
+
+    fn seq(&mut self, pattern : &[Pattern], index : usize, k : &mut dyn FnMut(&mut Self, usize)) {
+        match pattern.split_first() {
+            None => k(self, index),
+            Some((p, rest)) => self.one(p, index, &mut |m, next| m.seq(rest, next, k)),
+        }
+    }
+
+    fn one(&mut self, pattern : &Pattern, index : usize, k : &mut dyn FnMut(&mut Self, usize)) {
+        match pattern {
+            Pattern::Seq(ps) => self.seq(ps, index, k),
+            Pattern::Alt(ps) => {
+                for (branch, p) in ps.iter().enumerate() {
+                    self.branches.push(branch);
+                    self.one(p, index, k);
+                    self.branches.pop();
+                }
+            },
+            Pattern::Capture(name, p) => self.one(p, index, &mut |m, next| {
+                m.captures.push((name.clone(), index..next));
+                k(m, next);
+                m.captures.pop();
+            }),
+            Pattern::Parse(name, p) => self.one(p, index, &mut |m, next| {
+                if next == index + 1 {
+                    m.parses.push((index, name.clone()));
+                    k(m, next);
+                    m.parses.pop();
+                }
+            }),
+            Pattern::Expect(name, p) => {
+                let track = std::mem::replace(&mut self.track, false);
+                let mut matched = false;
+                self.one(p, index, &mut |m, next| {
+                    matched = true;
+                    m.track = track;
+                    k(m, next);
+                    m.track = false;
+                });
+                self.track = track;
+                if track && !matched {
+                    self.expect(index, name.clone());
+                }
+            },
+            p if index >= self.data.len() => {
+                self.incomplete = true;
+                if self.track {
+                    self.expect(index, p.describe());
+                }
+            },
+            Pattern::Adjacent | Pattern::Separated if index == 0 => { },
+            p @ (Pattern::Adjacent | Pattern::Separated) => {
+                let adjacent = self.data[index - 1].end_offset() == self.data[index].meta().start;
+                if adjacent == matches!(p, Pattern::Adjacent) {
+                    k(self, index);
+                }
+                else if self.track {
+                    self.expect(index, p.describe());
+                }
+            },
+            p if single_match(p, &self.data[index]) => k(self, index + 1),
+            p => {
+                if self.track {
+                    self.expect(index, p.describe());
+                }
+            },
         }
     }
-    true
 }
 
-pub struct Rule<T> {
+type ItemsAction<'a, T> = Box<dyn Fn(Vec<Bracket>) -> T + 'a>;
+type MatchAction<'a, T> = Box<dyn Fn(Match<Bracket>) -> T + 'a>;
+type NestedAction<'a, T> = Box<dyn Fn(Match<Bracket>, Vec<Vec<T>>) -> T + 'a>;
+
+enum Action<'a, T> {
+    Items(ItemsAction<'a, T>),
+    Match(MatchAction<'a, T>),
+    Nested(NestedAction<'a, T>),
+}
+
+/// Actions are `Fn` so that they can process input with the rules which they belong to.  Use a `Cell` or
+/// `RefCell` for state which they update.
+pub struct Rule<'a, T> {
     pattern : Vec<Pattern>, 
-    f : fn(Vec<Bracket>) -> T,
+    f : Action<'a, T>,
+    priority : i32,
+}
+
+impl<'a, T> Rule<'a, T> {
+    pub fn new(pattern : Vec<Pattern>, f : impl Fn(Vec<Bracket>) -> T + 'a) -> Rule<'a, T> {
+        Rule { pattern, f: Action::Items(Box::new(f)), priority: 0 }
+    }
+    pub fn with_match(pattern : Vec<Pattern>, f : impl Fn(Match<Bracket>) -> T + 'a) -> Rule<'a, T> {
+        Rule { pattern, f: Action::Match(Box::new(f)), priority: 0 }
+    }
+    /// The action also receives the results of each `Parse` pattern in the order that they were matched.
+    pub fn with_nested(pattern : Vec<Pattern>, f : impl Fn(Match<Bracket>, Vec<Vec<T>>) -> T + 'a) -> Rule<'a, T> {
+        Rule { pattern, f: Action::Nested(Box::new(f)), priority: 0 }
+    }
+    /// When several rules match the same items the ones with the highest priority are used.  Defaults to 0.
+    /// See `Strategy` for how priority interacts with the length of a match.
+    pub fn priority(mut self, priority : i32) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Strategy {
+    /// Applies the first rule that matches as soon as the buffer matches it.  Priority only decides between
+    /// rules which match the same buffer, so a longer rule with a higher priority never wins over a shorter
+    /// rule, and rules with the same priority are decided by their order instead of an `AmbiguousMatch` error.
+    #[default]
+    First,
+    /// Grows the buffer while any rule could still match more items and then applies the rule that
+    /// matched the most items.  Items after the longest match are processed again.  Matching rules
+    /// with the same length and priority are an `AmbiguousMatch` error.
+    Longest,
+}
+
+/// Named rule sets which can refer to each other with `Pattern::Parse`.
+pub struct Grammar<'a, T> {
+    rules : Vec<(String, Vec<Rule<'a, T>>)>,
+    strategy : Strategy,
 }
 
-impl<T> Rule<T> {
-    pub fn new(pattern : Vec<Pattern>, f : fn(Vec<Bracket>) -> T) -> Rule<T> {
-        Rule { pattern, f }
+impl<T> Default for Grammar<'_, T> {
+    fn default() -> Self {
+        Grammar { rules: vec![], strategy: Strategy::First }
     }
 }
 
-impl<T> From<(Vec<Pattern>, fn(Vec<Bracket>) -> T)> for Rule<T> {
+impl<'a, T> Grammar<'a, T> {
+    pub fn new() -> Self {
+        Grammar::default()
+    }
+    pub fn rules<S : AsRef<str>>(mut self, name : S, rules : Vec<Rule<'a, T>>) -> Self {
+        self.rules.push((name.as_ref().to_string(), rules));
+        self
+    }
+    pub fn strategy(mut self, strategy : Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+    pub fn process<I : Iterator<Item = Bracket>>(&self, name : &str, input : I) -> Result<Vec<T>, BracketProcessError> {
+        let rules = self.get(name)?;
+        process_rules(rules, Some(self), self.strategy, input)
+    }
+    fn get(&self, name : &str) -> Result<&[Rule<'a, T>], BracketProcessError> {
+        match self.rules.iter().find(|(n, _)| n == name) {
+            Some((_, rules)) => Ok(rules),
+            None => Err(BracketProcessError::UnknownRuleSet(name.to_string())),
+        }
+    }
+}
+
+impl<'a, T : 'a> From<(Vec<Pattern>, fn(Vec<Bracket>) -> T)> for Rule<'a, T> {
     fn from(value : (Vec<Pattern>, fn(Vec<Bracket>) -> T)) ->  Self {
         Rule::new(value.0, value.1)
     }
@@ -40,49 +369,243 @@ impl<T> From<(Vec<Pattern>, fn(Vec<Bracket>) -> T)> for Rule<T> {
 
 #[derive(Debug)]
 pub enum BracketProcessError {
-    CurrentBufferExceedsAllPatterns(Vec<Bracket>),
-    CurrentBufferCannotBeMatchedAgainstAnyPattern(Vec<Bracket>),
+    CurrentBufferExceedsAllPatterns(Vec<Bracket>, Vec<PartialMatch>),
+    CurrentBufferCannotBeMatchedAgainstAnyPattern(Vec<Bracket>, Vec<PartialMatch>),
+    /// No rule matches the buffer or could match it with more items.
+    NoMatch(Vec<Bracket>, Vec<PartialMatch>),
+    UnknownRuleSet(String),
+    /// The matched items and the indices of the rules which matched them.
+    AmbiguousMatch(Vec<Bracket>, Vec<usize>),
+}
+
+impl BracketProcessError {
+    pub fn meta(&self) -> LMeta {
+        match self {
+            BracketProcessError::CurrentBufferExceedsAllPatterns(xs, _) => buffer_meta(xs),
+            BracketProcessError::CurrentBufferCannotBeMatchedAgainstAnyPattern(xs, _) => buffer_meta(xs),
+            BracketProcessError::NoMatch(xs, _) => buffer_meta(xs),
+            BracketProcessError::UnknownRuleSet(_) => LMeta::new(),
+            BracketProcessError::AmbiguousMatch(xs, _) => buffer_meta(xs),
+        }
+    }
+    pub fn buffer(&self) -> &[Bracket] {
+        match self {
+            BracketProcessError::CurrentBufferExceedsAllPatterns(xs, _) => xs,
+            BracketProcessError::CurrentBufferCannotBeMatchedAgainstAnyPattern(xs, _) => xs,
+            BracketProcessError::NoMatch(xs, _) => xs,
+            BracketProcessError::UnknownRuleSet(_) => &[],
+            BracketProcessError::AmbiguousMatch(xs, _) => xs,
+        }
+    }
+    pub fn partial_matches(&self) -> &[PartialMatch] {
+        match self {
+            BracketProcessError::CurrentBufferExceedsAllPatterns(_, ps) => ps,
+            BracketProcessError::CurrentBufferCannotBeMatchedAgainstAnyPattern(_, ps) => ps,
+            BracketProcessError::NoMatch(_, ps) => ps,
+            BracketProcessError::UnknownRuleSet(_) => &[],
+            BracketProcessError::AmbiguousMatch(_, _) => &[],
+        }
+    }
+    /// The partial matches which got furthest into the buffer.
+    pub fn best_matches(&self) -> Vec<&PartialMatch> {
+        let partials = self.partial_matches();
+        let furthest = partials.iter().map(|p| p.matched).max().unwrap_or(0);
+        partials.iter().filter(|p| p.matched == furthest).collect()
+    }
+    /// Describes what was expected after the items which the best partial matches matched,
+    /// e.g. "expected `{` after `if (...)`".
+    pub fn expected(&self) -> Option<String> {
+        let best = self.best_matches();
+        let matched = best.first()?.matched;
+        let mut expected : Vec<&str> = vec![];
+        for e in best.iter().flat_map(|p| p.expected.iter()) {
+            if !expected.contains(&e.as_str()) {
+                expected.push(e);
+            }
+        }
+        if expected.is_empty() {
+            return None;
+        }
+        let expected = expected.join(" or ");
+        let buffer = self.buffer();
+        if matched == 0 {
+            Some(format!("expected {}", expected))
+        }
+        else {
+            let after = buffer[..matched].iter().map(brief).collect::<Vec<_>>().join(" ");
+            Some(format!("expected {} after `{}`", expected, after))
+        }
+    }
+    /// The location of the item which the best partial matches could not match or the end of
+    /// the buffer if they ran out of items.
+    pub fn expected_meta(&self) -> LMeta {
+        let buffer = self.buffer();
+        match self.best_matches().first().map(|p| p.matched) {
+            Some(matched) if matched < buffer.len() => buffer[matched].meta(),
+            _ => match buffer.last() {
+                Some(last) => LMeta::single(last.meta().end),
+                None => LMeta::new(),
+            },
+        }
+    }
+}
+
+pub(crate) fn buffer_meta(xs : &[Bracket]) -> LMeta {
+    match (xs.first(), xs.last()) {
+        (Some(first), Some(last)) => LMeta::multi(first.meta().start, last.meta().end),
+        _ => LMeta::new(),
+    }
 }
 
 impl std::fmt::Display for BracketProcessError {
     fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            BracketProcessError::CurrentBufferExceedsAllPatterns(xs) => 
-                write!(f, "The current buffer length exceeds all available pattern lengths: {:?}", xs),
-            BracketProcessError::CurrentBufferCannotBeMatchedAgainstAnyPattern(xs) => 
-                write!(f, "The current buffer does not match any pattern: {:?}", xs),
+            BracketProcessError::CurrentBufferExceedsAllPatterns(xs, _) => 
+                write!(f, "The current buffer length exceeds all available pattern lengths: {:?}", xs)?,
+            BracketProcessError::CurrentBufferCannotBeMatchedAgainstAnyPattern(xs, _) => 
+                write!(f, "The current buffer does not match any pattern: {:?}", xs)?,
+            BracketProcessError::NoMatch(xs, _) =>
+                write!(f, "No pattern matches the current buffer: {:?}", xs)?,
+            BracketProcessError::UnknownRuleSet(name) => 
+                write!(f, "The grammar does not have a rule set named: {}", name)?,
+            BracketProcessError::AmbiguousMatch(xs, rules) => 
+                write!(f, "The current buffer matches rules {:?} with the same priority: {:?}", rules, xs)?,
+        }
+        match self.expected() {
+            Some(expected) => write!(f, "; {}", expected),
+            None => Ok(()),
         }
     }
 }
 
 impl std::error::Error for BracketProcessError { }
 
-pub fn process<T, I : Iterator<Item = Bracket>>(rules : &[Rule<T>], mut input : I) -> Result<Vec<T>, BracketProcessError> {
-    let max = rules.iter().map(|r| r.pattern.len()).max().unwrap_or(0);
+pub fn process<T, I : Iterator<Item = Bracket>>(rules : &[Rule<'_, T>], input : I) -> Result<Vec<T>, BracketProcessError> {
+    process_rules(rules, None, Strategy::First, input)
+}
+
+pub fn process_with<T, I : Iterator<Item = Bracket>>( rules : &[Rule<'_, T>]
+                                                    , strategy : Strategy
+                                                    , input : I
+                                                    ) -> Result<Vec<T>, BracketProcessError> {
+    process_rules(rules, None, strategy, input)
+}
+
+fn process_nested<T>(item : &Bracket, name : &str, grammar : Option<&Grammar<'_, T>>) -> Result<Vec<T>, BracketProcessError> {
+    let grammar = match grammar {
+        Some(grammar) => grammar,
+        None => { return Err(BracketProcessError::UnknownRuleSet(name.to_string())); },
+    };
+    let contents = match item {
+        Bracket::Paren(_, xs) => xs.clone(),
+        Bracket::Angle(_, xs) => xs.clone(),
+        Bracket::Curl(_, xs) => xs.clone(),
+        Bracket::Square(_, xs) => xs.clone(),
+        x => vec![x.clone()],
+    };
+    grammar.process(name, contents.into_iter())
+}
+
+/// A rule which fully matched the current buffer.
+type Candidate = (usize, Found, Parses);
+
+fn process_rules<T, I : Iterator<Item = Bracket>>( rules : &[Rule<'_, T>]
+                                                 , grammar : Option<&Grammar<'_, T>>
+                                                 , strategy : Strategy
+                                                 , mut input : I
+                                                 ) -> Result<Vec<T>, BracketProcessError> {
+    let max = rules.iter().map(|r| r.pattern.iter().map(max_len).sum::<usize>()).max().unwrap_or(0);
     let mut match_buffer : Vec<Bracket> = vec![];
+    let mut lookahead : VecDeque<Bracket> = VecDeque::new();
+    let mut longest : Option<(usize, Vec<Candidate>)> = None;
     let mut ret : Vec<T> = vec![];
 
     loop {
-        if match_buffer.len() > max {
-            return Err(BracketProcessError::CurrentBufferExceedsAllPatterns(match_buffer));
+        let mut incomplete = false;
+        let mut found : Vec<Candidate> = vec![];
+        for (index, rule) in rules.iter().enumerate() {
+            if match_buffer.is_empty() {
+                break;
+            }
+            let (m, rule_incomplete) = pattern_match(&rule.pattern, &match_buffer);
+            incomplete |= rule_incomplete;
+            if let Some((m, parses)) = m {
+                found.push((index, m, parses));
+            }
+        }
+
+        let best = found.iter().map(|(index, _, _)| rules[*index].priority).max();
+        found.retain(|(index, _, _)| Some(rules[*index].priority) == best);
+        match strategy {
+            Strategy::First if !found.is_empty() => {
+                let (index, m, parses) = found.swap_remove(0);
+                let items = std::mem::take(&mut match_buffer);
+                ret.push(apply(&rules[index], grammar, m, parses, items)?);
+            },
+            Strategy::Longest if !found.is_empty() => { longest = Some((match_buffer.len(), found)); },
+            _ => { },
         }
-        for rule in rules {
-            if rule.pattern.len() == match_buffer.len() && pattern_match(&rule.pattern, &match_buffer) {
-                ret.push((rule.f)(std::mem::replace(&mut match_buffer, vec![])));
+
+        if match_buffer.is_empty() || incomplete {
+            if let Some(x) = lookahead.pop_front().or_else(|| input.next()) {
+                match_buffer.push(x);
+                continue;
             }
-        }    
-        match input.next() {
-            None if match_buffer.len() > 0 => { 
-                return Err(BracketProcessError::CurrentBufferCannotBeMatchedAgainstAnyPattern(match_buffer)); 
+        }
+        if match_buffer.is_empty() {
+            break;
+        }
+
+        match longest.take() {
+            Some((len, candidates)) if candidates.len() > 1 => {
+                match_buffer.truncate(len);
+                let indices = candidates.into_iter().map(|(index, _, _)| index).collect();
+                return Err(BracketProcessError::AmbiguousMatch(match_buffer, indices));
+            },
+            Some((len, mut candidates)) => {
+                for x in match_buffer.drain(len..).rev() {
+                    lookahead.push_front(x);
+                }
+                let (index, m, parses) = candidates.swap_remove(0);
+                let items = std::mem::take(&mut match_buffer);
+                ret.push(apply(&rules[index], grammar, m, parses, items)?);
+            },
+            None if incomplete => {
+                let partials = partial_matches(rules, &match_buffer);
+                return Err(BracketProcessError::CurrentBufferCannotBeMatchedAgainstAnyPattern(match_buffer, partials));
+            },
+            None if match_buffer.len() > max => {
+                let partials = partial_matches(rules, &match_buffer);
+                return Err(BracketProcessError::CurrentBufferExceedsAllPatterns(match_buffer, partials));
+            },
+            None => {
+                let partials = partial_matches(rules, &match_buffer);
+                return Err(BracketProcessError::NoMatch(match_buffer, partials));
             },
-            None => { break; }
-            Some(x) => { match_buffer.push(x); },
         }
     }
 
     Ok(ret)
 }
 
+fn apply<T>( rule : &Rule<'_, T>
+           , grammar : Option<&Grammar<'_, T>>
+           , Found { branches, captures, .. } : Found
+           , parses : Parses
+           , items : Vec<Bracket>
+           ) -> Result<T, BracketProcessError> {
+    Ok(match &rule.f {
+        Action::Items(f) => f(items),
+        Action::Match(f) => f(Match { items, branches, captures }),
+        Action::Nested(f) => {
+            let nested = parses.iter()
+                               .map(|(index, name)| process_nested(&items[*index], name, grammar))
+                               .collect::<Result<Vec<_>, _>>()?;
+            f(Match { items, branches, captures }, nested)
+        },
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -91,6 +614,7 @@ mod test {
     use crate::data::*;
 
     #[test]
+    #[allow(clippy::needless_borrow)]
     fn should_process_brackets() {
         fn is_paren(x : &Bracket) -> bool { matches!(x, Bracket::Paren(_, _)) }
         fn is_curl(x : &Bracket) -> bool { matches!(x, Bracket::Curl(_, _)) }
@@ -113,4 +637,258 @@ mod test {
         assert_eq!(output[0], 0);
         assert_eq!(output[1], 1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn should_report_alt_branches() {
+        fn is_paren(x : &Bracket) -> bool { matches!(x, Bracket::Paren(_, _)) }
+        fn is_curl(x : &Bracket) -> bool { matches!(x, Bracket::Curl(_, _)) }
+        fn sym(s : &str) -> Pattern { Pattern::Exact(Bracket::Lex(Lexeme::Symbol(LMeta::new(), s.to_string()))) }
+
+        let input = "while (x) { } if (y) { } else { } loop { }";
+        let tokens = lexer::lex(input).unwrap().into_iter();
+        let brackets = bracketer::bracket(tokens).unwrap().into_iter();
+
+        let rule = Rule::with_match(vec![Pattern::Alt(vec![ Pattern::Seq(vec![ Pattern::Alt(vec![sym("while"), sym("if")])
+                                                                              , Pattern::Pred(is_paren)
+                                                                              ])
+                                                           , sym("else")
+                                                           , sym("loop")
+                                                           ]), 
+                                         Pattern::Pred(is_curl)],
+                                    |m| (m.branches, m.items.len()));
+        let output = process(&[rule], brackets).unwrap();
+
+        assert_eq!(output, vec![(vec![0, 0], 3), (vec![0, 1], 3), (vec![1], 2), (vec![2], 2)]);
+    }
+
+    #[test]
+    fn should_take_first_alt_under_first_strategy() {
+        let rules = [Rule::new(vec![Pattern::Alt(vec![Pattern::Wild, Pattern::Seq(vec![Pattern::Wild, Pattern::Wild])])], |xs : Vec<Bracket>| xs.len())];
+        let input = bracketer::bracket(lexer::lex("a b c").unwrap().into_iter()).unwrap();
+        assert_eq!(process_with(&rules, Strategy::First, input.clone().into_iter()).unwrap(), vec![1, 1, 1]);
+        assert_eq!(process_with(&rules, Strategy::Longest, input.into_iter()).unwrap(), vec![2, 1]);
+    }
+
+    #[test]
+    fn should_capture_by_name() {
+        fn is_paren(x : &Bracket) -> bool { matches!(x, Bracket::Paren(_, _)) }
+        fn is_curl(x : &Bracket) -> bool { matches!(x, Bracket::Curl(_, _)) }
+        fn capture(name : &str, p : Pattern) -> Pattern { Pattern::Capture(name.into(), Box::new(p)) }
+
+        let input = "if (stuff) { a = 1; } else { c = y(1, 2, 3); }";
+        let tokens = lexer::lex(input).unwrap().into_iter();
+        let brackets = bracketer::bracket(tokens).unwrap().into_iter();
+        let if_sym = Bracket::Lex(Lexeme::Symbol(LMeta::new(), "if".to_string()));
+        let else_sym = Bracket::Lex(Lexeme::Symbol(LMeta::new(), "else".to_string()));
+
+        let if_rule = Rule::with_match(vec![Pattern::Exact(if_sym), capture("test", Pattern::Pred(is_paren)), capture("body", Pattern::Pred(is_curl))], 
+                                       |m| (m.single("test").unwrap().meta(), m.single("body").unwrap().meta()));
+        let else_rule = Rule::with_match(vec![Pattern::Exact(else_sym), capture("body", Pattern::Pred(is_curl))],
+                                         |m| (m.items[0].meta(), m.single("body").unwrap().meta()));
+        let rules = vec![if_rule, else_rule];
+
+        let output = process(&rules, brackets).unwrap();
+
+        assert_eq!(output, vec![(LMeta::multi(3, 9), LMeta::multi(11, 20)), (LMeta::multi(22, 25), LMeta::multi(27, 45))]);
+    }
+
+    #[test]
+    fn should_process_with_closures() {
+        use std::rc::Rc;
+        use std::cell::RefCell;
+        use crate::matching::Predicate;
+
+        let input = "let a = 1; let b = 2; a";
+        let tokens = lexer::lex(input).unwrap().into_iter();
+        let brackets = bracketer::bracket(tokens).unwrap().into_iter();
+
+        let symbols = Rc::new(RefCell::new(vec![]));
+        let known = symbols.clone();
+        let declared = symbols.clone();
+
+        let let_sym = Bracket::Lex(Lexeme::Symbol(LMeta::new(), "let".to_string()));
+        let semi = Bracket::Lex(Lexeme::Punct(LMeta::new(), ';'));
+        let let_rule = Rule::new(vec![Pattern::Exact(let_sym), Pattern::Wild, Pattern::Wild, Pattern::Wild, Pattern::Exact(semi)], 
+                                 move |xs| { declared.borrow_mut().push(xs[1].clone()); 0 });
+        let use_rule = Rule::new(vec![Pattern::Closure(Predicate::new(move |x| known.borrow().iter().any(|s : &Bracket| s.lmatch(x))))],
+                                 |_| 1);
+        let rules = vec![let_rule, use_rule];
+
+        let output = process(&rules, brackets).unwrap();
+
+        assert_eq!(output, vec![0, 0, 1]);
+        assert_eq!(symbols.borrow().len(), 2);
+    }
+
+    #[test]
+    fn should_process_with_borrowing_and_reentrant_actions() {
+        use std::rc::Rc;
+        use std::cell::OnceCell;
+
+        let brackets = |input : &str| bracketer::bracket(lexer::lex(input).unwrap().into_iter()).unwrap();
+
+        let keywords = ["let".to_string(), "fn".to_string()];
+        let rules = vec![Rule::new(vec![Pattern::Wild], |xs| keywords.contains(&xs[0].value()))];
+        assert_eq!(process(&rules, brackets("let x fn").into_iter()).unwrap(), vec![true, false, true]);
+
+        let cell : Rc<OnceCell<Vec<Rule<usize>>>> = Rc::new(OnceCell::new());
+        let inner = Rc::downgrade(&cell);
+        let depth = Rule::new(vec![Pattern::Pred(|x| matches!(x, Bracket::Paren(_, _)))], move |xs| {
+            let rules = inner.upgrade().unwrap();
+            let contents = xs[0].contents().unwrap().to_vec();
+            process(rules.get().unwrap(), contents.into_iter()).unwrap().into_iter().sum::<usize>() + 1
+        });
+        let _ = cell.set(vec![depth, Rule::new(vec![Pattern::Wild], |_| 0)]);
+        assert_eq!(process(cell.get().unwrap(), brackets("x ((y)) (a (b) c)").into_iter()).unwrap(), vec![0, 2, 2]);
+    }
+
+    #[test]
+    fn should_process_nested_brackets_with_grammar() {
+        #[derive(Debug, PartialEq)]
+        enum Ast {
+            Var(String),
+            Assign(String, String),
+            If(Vec<Ast>, Vec<Ast>),
+        }
+
+        fn is_paren(x : &Bracket) -> bool { matches!(x, Bracket::Paren(_, _)) }
+        fn is_curl(x : &Bracket) -> bool { matches!(x, Bracket::Curl(_, _)) }
+        fn is_sym(x : &Bracket) -> bool { matches!(x, Bracket::Lex(Lexeme::Symbol(_, _))) }
+        fn is_num(x : &Bracket) -> bool { matches!(x, Bracket::Lex(Lexeme::Number(_, _))) }
+        fn lex(x : &Bracket) -> String { match x { Bracket::Lex(l) => l.value(), _ => unreachable!() } }
+
+        let input = "if (x) { a = 1; if (y) { b = 2; } } c = 3;";
+        let tokens = lexer::lex(input).unwrap().into_iter();
+        let brackets = bracketer::bracket(tokens).unwrap().into_iter();
+        let if_sym = Bracket::Lex(Lexeme::Symbol(LMeta::new(), "if".to_string()));
+        let eq = Bracket::Lex(Lexeme::Punct(LMeta::new(), '='));
+        let semi = Bracket::Lex(Lexeme::Punct(LMeta::new(), ';'));
+
+        let grammar = Grammar::new()
+            .rules("stmt", vec![ Rule::with_nested( vec![ Pattern::Exact(if_sym)
+                                                        , Pattern::Parse("expr".into(), Box::new(Pattern::Pred(is_paren)))
+                                                        , Pattern::Parse("stmt".into(), Box::new(Pattern::Pred(is_curl)))
+                                                        ]
+                                                  , |_, mut nested| { 
+                                                        let body = nested.pop().unwrap();
+                                                        let test = nested.pop().unwrap();
+                                                        Ast::If(test, body) 
+                                                  })
+                               , Rule::new( vec![Pattern::Pred(is_sym), Pattern::Exact(eq), Pattern::Pred(is_num), Pattern::Exact(semi)]
+                                          , |xs| Ast::Assign(lex(&xs[0]), lex(&xs[2])))
+                               ])
+            .rules("expr", vec![Rule::new(vec![Pattern::Pred(is_sym)], |xs| Ast::Var(lex(&xs[0])))]);
+
+        let output = grammar.process("stmt", brackets).unwrap();
+
+        let expected = vec![ Ast::If( vec![Ast::Var("x".into())]
+                                    , vec![ Ast::Assign("a".into(), "1".into())
+                                          , Ast::If(vec![Ast::Var("y".into())], vec![Ast::Assign("b".into(), "2".into())])
+                                          ])
+                           , Ast::Assign("c".into(), "3".into())
+                           ];
+        assert_eq!(output, expected);
+
+        let error = grammar.process("missing", vec![].into_iter()).unwrap_err();
+        assert!(matches!(error, BracketProcessError::UnknownRuleSet(name) if name == "missing"));
+
+        let unused = [Rule::new(vec![Pattern::Parse("missing".into(), Box::new(Pattern::Pred(is_paren)))], |xs| xs.len())];
+        let brackets = bracketer::bracket(lexer::lex("(a b)").unwrap().into_iter()).unwrap().into_iter();
+        assert_eq!(process(&unused, brackets).unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn should_process_with_longest_match_and_priority() {
+        fn is_paren(x : &Bracket) -> bool { matches!(x, Bracket::Paren(_, _)) }
+        fn is_curl(x : &Bracket) -> bool { matches!(x, Bracket::Curl(_, _)) }
+        fn sym(s : &str) -> Pattern { Pattern::Exact(Bracket::Lex(Lexeme::Symbol(LMeta::new(), s.to_string()))) }
+
+        let rules = || vec![ Rule::new(vec![sym("else"), Pattern::Pred(is_curl)], |_| "else")
+                           , Rule::new(vec![sym("else"), sym("if"), Pattern::Pred(is_paren), Pattern::Pred(is_curl)], |_| "else if")
+                           , Rule::new(vec![sym("if")], |_| "if")
+                           , Rule::new(vec![sym("if"), Pattern::Pred(is_paren), sym("then")], |_| "if then")
+                           , Rule::new(vec![Pattern::Pred(is_paren)], |_| "paren")
+                           , Rule::new(vec![Pattern::Pred(is_curl)], |_| "curl")
+                           ];
+        let brackets = |input : &str| bracketer::bracket(lexer::lex(input).unwrap().into_iter()).unwrap();
+
+        let output = process_with(&rules(), Strategy::Longest, brackets("else {} else if (a) {} if (b) then if (c) {}").into_iter()).unwrap();
+        assert_eq!(output, vec!["else", "else if", "if then", "if", "paren", "curl"]);
+
+        let output = process(&rules(), brackets("if (b) then").into_iter());
+        assert!(matches!(output, Err(BracketProcessError::NoMatch(_, _))));
+
+        let output = process::<&str, _>(&[], brackets("if").into_iter());
+        assert!(matches!(output, Err(BracketProcessError::CurrentBufferExceedsAllPatterns(_, _))));
+
+        let rules = || vec![ Rule::new(vec![Pattern::Pred(is_paren)], |_| "paren")
+                           , Rule::new(vec![Pattern::Wild], |_| "wild")
+                           ];
+        let error = process_with(&rules(), Strategy::Longest, brackets("x (a)").into_iter()).unwrap_err();
+        assert!(matches!(&error, BracketProcessError::AmbiguousMatch(xs, rules) if xs.len() == 1 && *rules == vec![0, 1]));
+        assert_eq!(error.meta(), LMeta::multi(2, 4));
+
+        let rules = rules().into_iter().enumerate().map(|(i, r)| r.priority(i as i32)).collect::<Vec<_>>();
+        let output = process_with(&rules, Strategy::Longest, brackets("x (a)").into_iter()).unwrap();
+        assert_eq!(output, vec!["wild", "wild"]);
+        let output = process(&rules, brackets("x (a)").into_iter()).unwrap();
+        assert_eq!(output, vec!["wild", "wild"]);
+
+        let grammar = Grammar::new().strategy(Strategy::Longest).rules("top", vec![ Rule::new(vec![sym("a")], |_| 1)
+                                                                                  , Rule::new(vec![sym("a"), sym("b")], |_| 2)
+                                                                                  ]);
+        assert_eq!(grammar.process("top", brackets("a a b a").into_iter()).unwrap(), vec![1, 2, 1]);
+    }
+
+    #[test]
+    fn should_report_partial_matches() {
+        fn is_paren(x : &Bracket) -> bool { matches!(x, Bracket::Paren(_, _)) }
+        fn is_curl(x : &Bracket) -> bool { matches!(x, Bracket::Curl(_, _)) }
+        fn is_sym(x : &Bracket) -> bool { matches!(x, Bracket::Lex(Lexeme::Symbol(_, _))) }
+        fn sym(s : &str) -> Pattern { Pattern::Exact(Bracket::Lex(Lexeme::Symbol(LMeta::new(), s.to_string()))) }
+        fn punct(c : char) -> Pattern { Pattern::Exact(Bracket::Lex(Lexeme::Punct(LMeta::new(), c))) }
+        fn expect(name : &str, p : Pattern) -> Pattern { Pattern::Expect(name.into(), Box::new(p)) }
+
+        let rules = || vec![ Rule::new(vec![sym("if"), Pattern::Pred(is_paren), expect("`{`", Pattern::Pred(is_curl))], |_| 0)
+                           , Rule::new(vec![Pattern::Pred(is_sym), punct('='), Pattern::Wild, punct(';')], |_| 1)
+                           ];
+        let brackets = |input : &str| bracketer::bracket(lexer::lex(input).unwrap().into_iter()).unwrap();
+
+        let error = process(&rules(), brackets("x = 1; if (a) b").into_iter()).unwrap_err();
+        assert!(matches!(error, BracketProcessError::NoMatch(_, _)));
+        let expected = [ PartialMatch { rule: 0, matched: 2, expected: vec!["`{`".into()] }
+                       , PartialMatch { rule: 1, matched: 1, expected: vec!["`=`".into()] }
+                       ];
+        assert_eq!(error.partial_matches(), expected);
+        assert_eq!(error.expected().unwrap(), "expected `{` after `if (...)`");
+        assert_eq!(error.expected_meta(), LMeta::single(14));
+        assert_eq!(error.meta(), LMeta::multi(7, 14));
+
+        let error = process(&rules(), brackets("x = 1; y = 2").into_iter()).unwrap_err();
+        assert!(matches!(error, BracketProcessError::CurrentBufferCannotBeMatchedAgainstAnyPattern(_, _)));
+        assert_eq!(error.expected().unwrap(), "expected `;` after `y = 2`");
+        assert_eq!(error.expected_meta(), LMeta::single(11));
+
+        let error = process(&rules(), brackets("x = 1; ;").into_iter()).unwrap_err();
+        assert_eq!(error.expected().unwrap(), "expected `if` or an item matching a predicate");
+    }
+
+    #[test]
+    fn should_process_adjacent_and_separated_items() {
+        fn is_sym(x : &Bracket) -> bool { matches!(x, Bracket::Lex(Lexeme::Symbol(_, _))) }
+        fn colon() -> Pattern { Pattern::Exact(Bracket::Lex(Lexeme::Punct(LMeta::new(), ':'))) }
+
+        let rules = vec![ Rule::new(vec![ Pattern::Pred(is_sym), Pattern::Adjacent, colon(), Pattern::Adjacent, colon()
+                                        , Pattern::Adjacent, Pattern::Pred(is_sym)
+                                        ], |_| "path")
+                        , Rule::new(vec![Pattern::Pred(is_sym), colon(), Pattern::Separated, Pattern::Pred(is_sym)], |_| "typed")
+                        ];
+        let brackets = |input : &str| bracketer::bracket(lexer::lex(input).unwrap().into_iter()).unwrap();
+
+        let output = process(&rules, brackets("a::b x: y c::d").into_iter()).unwrap();
+        assert_eq!(output, vec!["path", "typed", "path"]);
+
+        let error = process(&rules, brackets("a: :b").into_iter()).unwrap_err();
+        assert_eq!(error.expected().unwrap(), "expected no space or an item matching a predicate after `a :`");
+    }
+}
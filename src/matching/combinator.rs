@@ -0,0 +1,359 @@
+
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+use crate::data::{Bracket, Lexeme, LMeta};
+
+/// Items which the combinators can consume.
+pub trait Item : Clone + 'static {
+    fn lexeme(&self) -> Option<&Lexeme>;
+    fn meta(&self) -> LMeta;
+}
+
+impl Item for Lexeme {
+    fn lexeme(&self) -> Option<&Lexeme> {
+        Some(self)
+    }
+    fn meta(&self) -> LMeta {
+        Lexeme::meta(self)
+    }
+}
+
+impl Item for Bracket {
+    fn lexeme(&self) -> Option<&Lexeme> {
+        match self {
+            Bracket::Lex(l) => Some(l),
+            _ => None,
+        }
+    }
+    fn meta(&self) -> LMeta {
+        Bracket::meta(self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    pub value : T,
+    pub meta : LMeta,
+}
+
+/// The location where parsing got furthest and the descriptions of what could have matched there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub meta : LMeta,
+    pub expected : Vec<String>,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Expected {} at: {}", self.expected.join(" or "), self.meta.start)
+    }
+}
+
+impl std::error::Error for ParseError { }
+
+struct Input<'a, I> {
+    items : &'a [I],
+    /// Where failures at the end of the items are reported.
+    end : LMeta,
+    furthest : &'a RefCell<Option<ParseError>>,
+}
+
+impl<I : Item> Input<'_, I> {
+    fn meta(&self, index : usize) -> LMeta {
+        self.items.get(index).map(|x| x.meta()).unwrap_or_else(|| self.end.clone())
+    }
+
+    fn span(&self, start : usize, end : usize) -> LMeta {
+        if end > start {
+            LMeta::multi(self.items[start].meta().start, self.items[end - 1].meta().end)
+        }
+        else {
+            LMeta::single(self.meta(start).start)
+        }
+    }
+
+    fn fail<T>(&self, index : usize, expected : &str) -> Option<T> {
+        let meta = self.meta(index);
+        let mut furthest = self.furthest.borrow_mut();
+        match &mut *furthest {
+            Some(e) if e.meta.start > meta.start => { },
+            Some(e) if e.meta.start == meta.start => {
+                if !e.expected.iter().any(|x| x == expected) {
+                    e.expected.push(expected.to_string());
+                }
+            },
+            _ => { *furthest = Some(ParseError { meta, expected: vec![expected.to_string()] }); },
+        }
+        None
+    }
+}
+
+type Run<I, T> = dyn Fn(&Input<I>, usize) -> Option<(T, usize)>;
+
+/// Consumes items from the start of a slice and produces a value.  Failures are collected while
+/// parsing and the one which got furthest is reported.
+pub struct Parser<I, T>(Rc<Run<I, T>>);
+
+impl<I, T> Clone for Parser<I, T> {
+    fn clone(&self) -> Self {
+        Parser(self.0.clone())
+    }
+}
+
+impl<I : Item, T : 'static> Parser<I, T> {
+    fn new(f : impl Fn(&Input<I>, usize) -> Option<(T, usize)> + 'static) -> Self {
+        Parser(Rc::new(f))
+    }
+
+    /// Parses all of the input.
+    pub fn parse(&self, input : &[I]) -> Result<Spanned<T>, ParseError> {
+        let furthest = RefCell::new(None);
+        let end = input.last().map(|x| LMeta::single(x.meta().end)).unwrap_or_default();
+        let input = Input { items: input, end, furthest: &furthest };
+        match (self.0)(&input, 0) {
+            Some((value, index)) if index == input.items.len() => { return Ok(Spanned { value, meta: input.span(0, index) }); },
+            Some((_, index)) => { input.fail::<()>(index, "end of input"); },
+            None => { },
+        }
+        let end = input.end;
+        Err(furthest.into_inner().unwrap_or(ParseError { meta: end, expected: vec![] }))
+    }
+}
+
+pub fn pred<I : Item>(expected : &str, f : impl Fn(&I) -> bool + 'static) -> Parser<I, I> {
+    let expected = expected.to_string();
+    Parser::new(move |input, index| match input.items.get(index) {
+        Some(x) if f(x) => Some((x.clone(), index + 1)),
+        _ => input.fail(index, &expected),
+    })
+}
+
+pub fn any<I : Item>() -> Parser<I, I> {
+    pred("any item", |_| true)
+}
+
+pub fn sym<I : Item>(s : &str) -> Parser<I, I> {
+    let symbol = s.to_string();
+    pred(&format!("`{}`", s), move |x : &I| matches!(x.lexeme(), Some(Lexeme::Symbol(_, v)) if *v == symbol))
+}
+
+pub fn punct<I : Item>(c : char) -> Parser<I, I> {
+    pred(&format!("`{}`", c), move |x : &I| matches!(x.lexeme(), Some(Lexeme::Punct(_, v)) if *v == c))
+}
+
+/// Any symbol, producing its text.
+pub fn symbol<I : Item>() -> Parser<I, String> {
+    map(pred("a symbol", |x : &I| matches!(x.lexeme(), Some(Lexeme::Symbol(_, _)))), |x| x.lexeme().unwrap().value())
+}
+
+/// Any number, producing its text.
+pub fn number<I : Item>() -> Parser<I, String> {
+    map(pred("a number", |x : &I| matches!(x.lexeme(), Some(Lexeme::Number(_, _)))), |x| x.lexeme().unwrap().value())
+}
+
+/// Any string, producing its contents.
+pub fn string<I : Item>() -> Parser<I, String> {
+    map(pred("a string", |x : &I| matches!(x.lexeme(), Some(Lexeme::String(_, _, _)))), |x| x.lexeme().unwrap().value())
+}
+
+fn contents<T : 'static>( expected : &'static str
+                        , close : &'static str
+                        , select : fn(&Bracket) -> Option<&Vec<Bracket>>
+                        , p : Parser<Bracket, T>
+                        ) -> Parser<Bracket, T> {
+    Parser::new(move |input, index| {
+        let item = input.items.get(index);
+        let xs = match item.and_then(select) {
+            Some(xs) => xs,
+            None => { return input.fail(index, expected); },
+        };
+        let inner = Input { items: xs, end: LMeta::single(input.meta(index).end), furthest: input.furthest };
+        match (p.0)(&inner, 0) {
+            Some((value, end)) if end == xs.len() => Some((value, index + 1)),
+            Some((_, end)) => inner.fail(end, close),
+            None => None,
+        }
+    })
+}
+
+/// Matches a paren and parses all of its contents with the parser.
+pub fn paren<T : 'static>(p : Parser<Bracket, T>) -> Parser<Bracket, T> {
+    contents("`(`", "`)`", |x| match x { Bracket::Paren(_, xs) => Some(xs), _ => None }, p)
+}
+
+pub fn angle<T : 'static>(p : Parser<Bracket, T>) -> Parser<Bracket, T> {
+    contents("`<`", "`>`", |x| match x { Bracket::Angle(_, xs) => Some(xs), _ => None }, p)
+}
+
+pub fn curl<T : 'static>(p : Parser<Bracket, T>) -> Parser<Bracket, T> {
+    contents("`{`", "`}`", |x| match x { Bracket::Curl(_, xs) => Some(xs), _ => None }, p)
+}
+
+pub fn square<T : 'static>(p : Parser<Bracket, T>) -> Parser<Bracket, T> {
+    contents("`[`", "`]`", |x| match x { Bracket::Square(_, xs) => Some(xs), _ => None }, p)
+}
+
+pub fn map<I : Item, T : 'static, U : 'static>(p : Parser<I, T>, f : impl Fn(T) -> U + 'static) -> Parser<I, U> {
+    Parser::new(move |input, index| (p.0)(input, index).map(|(value, end)| (f(value), end)))
+}
+
+/// Pairs the value with the span of the items that produced it.
+pub fn spanned<I : Item, T : 'static>(p : Parser<I, T>) -> Parser<I, Spanned<T>> {
+    Parser::new(move |input, index|
+        (p.0)(input, index).map(|(value, end)| (Spanned { value, meta: input.span(index, end) }, end)))
+}
+
+/// Tries the second parser from the same position when the first fails.
+pub fn or<I : Item, T : 'static>(a : Parser<I, T>, b : Parser<I, T>) -> Parser<I, T> {
+    Parser::new(move |input, index| (a.0)(input, index).or_else(|| (b.0)(input, index)))
+}
+
+pub fn opt<I : Item, T : 'static>(p : Parser<I, T>) -> Parser<I, Option<T>> {
+    Parser::new(move |input, index| match (p.0)(input, index) {
+        Some((value, end)) => Some((Some(value), end)),
+        None => Some((None, index)),
+    })
+}
+
+pub fn then<I : Item, A : 'static, B : 'static>(a : Parser<I, A>, b : Parser<I, B>) -> Parser<I, (A, B)> {
+    Parser::new(move |input, index| {
+        let (x, index) = (a.0)(input, index)?;
+        let (y, index) = (b.0)(input, index)?;
+        Some(((x, y), index))
+    })
+}
+
+/// Keeps the value of the first parser.
+pub fn left<I : Item, A : 'static, B : 'static>(a : Parser<I, A>, b : Parser<I, B>) -> Parser<I, A> {
+    map(then(a, b), |(x, _)| x)
+}
+
+/// Keeps the value of the second parser.
+pub fn right<I : Item, A : 'static, B : 'static>(a : Parser<I, A>, b : Parser<I, B>) -> Parser<I, B> {
+    map(then(a, b), |(_, y)| y)
+}
+
+/// Stops at the first failure or when the parser stops consuming items.
+pub fn many<I : Item, T : 'static>(p : Parser<I, T>) -> Parser<I, Vec<T>> {
+    Parser::new(move |input, mut index| {
+        let mut ret = vec![];
+        while let Some((value, end)) = (p.0)(input, index) {
+            if end == index {
+                break;
+            }
+            ret.push(value);
+            index = end;
+        }
+        Some((ret, index))
+    })
+}
+
+pub fn many1<I : Item, T : 'static>(p : Parser<I, T>) -> Parser<I, Vec<T>> {
+    map(then(p.clone(), many(p)), |(x, mut xs)| { xs.insert(0, x); xs })
+}
+
+/// Zero or more items separated by the separator without a trailing separator.
+pub fn sep_by<I : Item, T : 'static, S : 'static>(p : Parser<I, T>, sep : Parser<I, S>) -> Parser<I, Vec<T>> {
+    let rest = many(right(sep, p.clone()));
+    map(opt(then(p, rest)), |x| match x {
+        Some((x, mut xs)) => { xs.insert(0, x); xs },
+        None => vec![],
+    })
+}
+
+type Slot<I, T> = RefCell<Option<Weak<Run<I, T>>>>;
+
+/// Defines a parser which refers to itself, like an expression inside of parens.  The parser passed to `f`
+/// only holds a weak reference to the returned parser, so it fails once the returned parser is dropped.
+pub fn recursive<I : Item, T : 'static>(f : impl FnOnce(Parser<I, T>) -> Parser<I, T>) -> Parser<I, T> {
+    let slot : Rc<Slot<I, T>> = Rc::new(RefCell::new(None));
+    let inner = slot.clone();
+    let this = Parser::new(move |input, index| {
+        let p = inner.borrow().as_ref().and_then(|w| w.upgrade());
+        match p {
+            Some(p) => p(input, index),
+            None => input.fail(index, "a recursive parser which is still defined"),
+        }
+    });
+    let ret = f(this);
+    *slot.borrow_mut() = Some(Rc::downgrade(&ret.0));
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parsing::{lexer, bracketer};
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Ast {
+        If(String, Vec<Ast>),
+        Call(String, Vec<Ast>),
+        Num(String),
+        Var(String),
+    }
+
+    fn brackets(input : &str) -> Vec<Bracket> {
+        bracketer::bracket(lexer::lex(input).unwrap().into_iter()).unwrap()
+    }
+
+    fn stmts() -> Parser<Bracket, Vec<Ast>> {
+        let expr = recursive(|expr| {
+            let call = map(then(symbol(), paren(sep_by(expr, punct(',')))), |(name, args)| Ast::Call(name, args));
+            or(call, or(map(number(), Ast::Num), map(symbol(), Ast::Var)))
+        });
+        recursive(|stmts| {
+            let if_stmt = map(then(right(sym("if"), paren(symbol())), curl(stmts)), |(test, body)| Ast::If(test, body));
+            many(or(if_stmt, left(expr, punct(';'))))
+        })
+    }
+
+    #[test]
+    fn should_parse_lexemes() {
+        let input = lexer::lex("1, 2, 3").unwrap();
+        let output = sep_by(number(), punct(',')).parse(&input).unwrap();
+        assert_eq!(output, Spanned { value: vec!["1".to_string(), "2".into(), "3".into()], meta: LMeta::multi(0, 6) });
+
+        let input = lexer::lex("let x = 5").unwrap();
+        let parser = then(right(sym("let"), spanned(symbol())), opt(right(punct('='), number())));
+        let output = parser.parse(&input).unwrap().value;
+        assert_eq!(output, (Spanned { value: "x".to_string(), meta: LMeta::single(4) }, Some("5".to_string())));
+        assert_eq!(parser.parse(&input[..2]).unwrap().value.1, None);
+        assert_eq!(many1(number::<Lexeme>()).parse(&[]), Err(ParseError { meta: LMeta::new(), expected: vec!["a number".into()] }));
+    }
+
+    #[test]
+    fn should_parse_nested_brackets() {
+        let input = brackets("if (x) { f(1, g(y)); } z;");
+        let output = stmts().parse(&input).unwrap();
+        let expected = vec![ Ast::If("x".into(), vec![Ast::Call("f".into(), vec![ Ast::Num("1".into())
+                                                                                 , Ast::Call("g".into(), vec![Ast::Var("y".into())])
+                                                                                 ])])
+                           , Ast::Var("z".into())
+                           ];
+        assert_eq!(output.value, expected);
+        assert_eq!(output.meta, LMeta::multi(0, 24));
+    }
+
+    #[test]
+    fn should_report_furthest_failure() {
+        let input = brackets("if (x) { f(1 2); }");
+        let error = stmts().parse(&input).unwrap_err();
+        assert_eq!(error, ParseError { meta: LMeta::single(13), expected: vec!["`,`".into(), "`)`".into()] });
+
+        let input = brackets("if (x) { y }");
+        let error = stmts().parse(&input).unwrap_err();
+        assert_eq!(error, ParseError { meta: LMeta::single(11), expected: vec!["`(`".into(), "`;`".into()] });
+
+        let mut inner = None;
+        let parser = recursive(|this : Parser<Lexeme, Vec<String>>| {
+            inner = Some(this.clone());
+            many(symbol())
+        });
+        let input = lexer::lex("a b").unwrap();
+        assert!(parser.parse(&input).is_ok());
+        drop(parser);
+        let error = inner.unwrap().parse(&input).unwrap_err();
+        assert_eq!(error.expected, vec!["a recursive parser which is still defined".to_string()]);
+    }
+}
@@ -0,0 +1,292 @@
+
+use std::rc::Rc;
+use crate::data::{Bracket, Lexeme, LMeta};
+use crate::parsing::lexer::{LexerConfig, LexError};
+use crate::parsing::bracketer::{self, BracketError};
+use super::Predicate;
+use super::bracket;
+use super::lexeme;
+
+#[derive(Debug)]
+pub enum DslError {
+    Lex(LexError),
+    Bracket(BracketError),
+    /// A `$` which is not followed by a symbol.
+    MissingPlaceholderName(LMeta),
+    UnknownPlaceholder(LMeta, String),
+}
+
+impl DslError {
+    pub fn meta(&self) -> LMeta {
+        match self {
+            DslError::Lex(e) => e.meta(),
+            DslError::Bracket(e) => e.meta(),
+            DslError::MissingPlaceholderName(m) => m.clone(),
+            DslError::UnknownPlaceholder(m, _) => m.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for DslError {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DslError::Lex(e) => write!(f, "{}", e),
+            DslError::Bracket(e) => write!(f, "{}", e),
+            DslError::MissingPlaceholderName(m) => write!(f, "Expected a placeholder name after `$`: {}", m.start),
+            DslError::UnknownPlaceholder(m, name) => write!(f, "Encountered unknown placeholder `${}`: {}", name, m.start),
+        }
+    }
+}
+
+impl std::error::Error for DslError { }
+
+type LexemeBinding = Rc<dyn Fn(&Lexeme) -> bool>;
+type BracketBinding = Rc<dyn Fn(&Bracket) -> bool>;
+
+/// Compiles pattern source text into patterns.  The source is lexed with the lexer config and each
+/// lexeme matches an equal lexeme, except for placeholders.  A placeholder `$name` matches one item
+/// with the predicate bound to `name` and captures it as `name`.  `$any` matches any item and `$$`
+/// matches a `$`.  The predefined placeholders are `$num`, `$sym`, `$str` and `$punct`.  Items which touch
+/// in the source, like the two `=` of `==`, have to touch in the input as well.
+///
+/// In bracket patterns `(..)`, `<..>`, `{..}` and `[..]` match any bracket of that kind, while other
+/// bracket contents are a pattern that must match all of the bracket's contents.  Lexeme patterns can
+/// be used as one item of a lexeme pattern array with `lexeme::Pattern::Seq`.
+pub struct Dsl {
+    config : LexerConfig,
+    lexemes : Vec<(String, LexemeBinding)>,
+    brackets : Vec<(String, BracketBinding)>,
+}
+
+impl Default for Dsl {
+    fn default() -> Self {
+        Dsl { config: LexerConfig::default(), lexemes: vec![], brackets: vec![] }
+            .bind("num", |x| matches!(x, Lexeme::Number(_, _)))
+            .bind("sym", |x| matches!(x, Lexeme::Symbol(_, _)))
+            .bind("str", |x| matches!(x, Lexeme::String(_, _, _)))
+            .bind("punct", |x| matches!(x, Lexeme::Punct(_, _)))
+    }
+}
+
+impl Dsl {
+    pub fn new() -> Self {
+        Dsl::default()
+    }
+    pub fn lexer(mut self, config : LexerConfig) -> Self {
+        self.config = config;
+        self
+    }
+    /// Binds a placeholder for both lexeme and bracket patterns.  In bracket patterns it only matches lexemes.
+    pub fn bind<S : AsRef<str>>(mut self, name : S, f : impl Fn(&Lexeme) -> bool + 'static) -> Self {
+        self.lexemes.insert(0, (name.as_ref().to_string(), Rc::new(f)));
+        self
+    }
+    /// Binds a placeholder for bracket patterns only.
+    pub fn bind_bracket<S : AsRef<str>>(mut self, name : S, f : impl Fn(&Bracket) -> bool + 'static) -> Self {
+        self.brackets.insert(0, (name.as_ref().to_string(), Rc::new(f)));
+        self
+    }
+
+    pub fn lexeme(&self, source : &str) -> Result<Vec<lexeme::Pattern>, DslError> {
+        let input = self.config.lex(source).map_err(DslError::Lex)?;
+        let mut ret = vec![];
+        let mut input = input.into_iter();
+        let mut end = None;
+        while let Some(l) = input.next() {
+            if end == Some(l.meta().start) {
+                ret.push(lexeme::Pattern::Adjacent);
+            }
+            end = Some(l.end_offset());
+            let pattern = match placeholder(&l, &mut || input.next().inspect(|x| end = Some(x.end_offset())))? {
+                None => lexeme::Pattern::Exact(l),
+                Some(Placeholder::Dollar(l)) => lexeme::Pattern::Exact(l),
+                Some(Placeholder::Name(_, name)) if name == "any" => capture_lexeme(name, lexeme::Pattern::Wild),
+                Some(Placeholder::Name(meta, name)) => {
+                    let f = self.lexeme_binding(&name).ok_or_else(|| DslError::UnknownPlaceholder(meta, name.clone()))?;
+                    capture_lexeme(name, lexeme::Pattern::Closure(Predicate::new(move |x| f(x))))
+                },
+            };
+            ret.push(pattern);
+        }
+        Ok(ret)
+    }
+
+    pub fn bracket(&self, source : &str) -> Result<Vec<bracket::Pattern>, DslError> {
+        let input = self.config.lex(source).map_err(DslError::Lex)?;
+        let input = bracketer::bracket(input.into_iter()).map_err(DslError::Bracket)?;
+        self.brackets(input)
+    }
+
+    fn brackets(&self, input : Vec<Bracket>) -> Result<Vec<bracket::Pattern>, DslError> {
+        use bracket::Pattern;
+
+        fn is_any(xs : &[Bracket]) -> bool {
+            matches!(xs, [Bracket::Lex(Lexeme::Punct(_, '.')), Bracket::Lex(Lexeme::Punct(_, '.'))])
+        }
+
+        let mut ret = vec![];
+        let mut input = input.into_iter().peekable();
+        let mut end = None;
+        while let Some(b) = input.next() {
+            if end == Some(b.meta().start) {
+                ret.push(Pattern::Adjacent);
+            }
+            end = Some(b.end_offset());
+            let pattern = match b {
+                Bracket::Paren(_, xs) if is_any(&xs) => Pattern::Pred(|x| matches!(x, Bracket::Paren(_, _))),
+                Bracket::Angle(_, xs) if is_any(&xs) => Pattern::Pred(|x| matches!(x, Bracket::Angle(_, _))),
+                Bracket::Curl(_, xs) if is_any(&xs) => Pattern::Pred(|x| matches!(x, Bracket::Curl(_, _))),
+                Bracket::Square(_, xs) if is_any(&xs) => Pattern::Pred(|x| matches!(x, Bracket::Square(_, _))),
+                Bracket::Paren(_, xs) => self.contents(xs, |x| match x { Bracket::Paren(_, ys) => Some(ys), _ => None })?,
+                Bracket::Angle(_, xs) => self.contents(xs, |x| match x { Bracket::Angle(_, ys) => Some(ys), _ => None })?,
+                Bracket::Curl(_, xs) => self.contents(xs, |x| match x { Bracket::Curl(_, ys) => Some(ys), _ => None })?,
+                Bracket::Square(_, xs) => self.contents(xs, |x| match x { Bracket::Square(_, ys) => Some(ys), _ => None })?,
+                Bracket::Lex(l) => {
+                    let mut next = || match input.next_if(|x| matches!(x, Bracket::Lex(_))) {
+                        Some(Bracket::Lex(l)) => {
+                            end = Some(l.end_offset());
+                            Some(l)
+                        },
+                        _ => None,
+                    };
+                    match placeholder(&l, &mut next)? {
+                        None => Pattern::Exact(Bracket::Lex(l)),
+                        Some(Placeholder::Dollar(l)) => Pattern::Exact(Bracket::Lex(l)),
+                        Some(Placeholder::Name(_, name)) if name == "any" => capture_bracket(name, Pattern::Wild),
+                        Some(Placeholder::Name(meta, name)) => capture_bracket(name.clone(), self.bracket_binding(meta, name)?),
+                    }
+                },
+                x @ Bracket::Error(_) => Pattern::Exact(x),
+            };
+            ret.push(pattern);
+        }
+        Ok(ret)
+    }
+
+    fn contents(&self, xs : Vec<Bracket>, select : fn(&Bracket) -> Option<&Vec<Bracket>>) -> Result<bracket::Pattern, DslError> {
+        let pattern = self.brackets(xs)?;
+        Ok(bracket::Pattern::Closure(Predicate::new(move |x| match select(x) {
+            Some(ys) => bracket::full_match(&pattern, ys),
+            None => false,
+        })))
+    }
+
+    fn lexeme_binding(&self, name : &str) -> Option<LexemeBinding> {
+        self.lexemes.iter().find(|(n, _)| n == name).map(|(_, f)| f.clone())
+    }
+
+    fn bracket_binding(&self, meta : LMeta, name : String) -> Result<bracket::Pattern, DslError> {
+        if let Some((_, f)) = self.brackets.iter().find(|(n, _)| *n == name) {
+            let f = f.clone();
+            return Ok(bracket::Pattern::Closure(Predicate::new(move |x| f(x))));
+        }
+        match self.lexeme_binding(&name) {
+            Some(f) => Ok(bracket::Pattern::Closure(Predicate::new(move |x| matches!(x, Bracket::Lex(l) if f(l))))),
+            None => Err(DslError::UnknownPlaceholder(meta, name)),
+        }
+    }
+}
+
+enum Placeholder {
+    /// A `$` written as `$$`.
+    Dollar(Lexeme),
+    Name(LMeta, String),
+}
+
+/// Reads the rest of a placeholder when the lexeme is a `$`.
+fn placeholder(l : &Lexeme, next : &mut dyn FnMut() -> Option<Lexeme>) -> Result<Option<Placeholder>, DslError> {
+    if !matches!(l, Lexeme::Punct(_, '$')) {
+        return Ok(None);
+    }
+    match next() {
+        Some(d @ Lexeme::Punct(_, '$')) => Ok(Some(Placeholder::Dollar(d))),
+        Some(Lexeme::Symbol(m, name)) => Ok(Some(Placeholder::Name(LMeta::multi(l.meta().start, m.end), name))),
+        _ => Err(DslError::MissingPlaceholderName(l.meta())),
+    }
+}
+
+fn capture_lexeme(name : String, pattern : lexeme::Pattern) -> lexeme::Pattern {
+    lexeme::Pattern::Capture(name, Box::new(pattern))
+}
+
+fn capture_bracket(name : String, pattern : bracket::Pattern) -> bracket::Pattern {
+    bracket::Pattern::Capture(name, Box::new(pattern))
+}
+
+/// Compiles a bracket pattern with the default `Dsl`.
+pub fn bracket_pattern(source : &str) -> Result<Vec<bracket::Pattern>, DslError> {
+    Dsl::default().bracket(source)
+}
+
+/// Compiles a lexeme pattern with the default `Dsl`.
+pub fn lexeme_pattern(source : &str) -> Result<Vec<lexeme::Pattern>, DslError> {
+    Dsl::default().lexeme(source)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parsing::lexer;
+    use crate::matching::bracket::Rule;
+    use crate::matching::Match;
+
+    fn brackets(input : &str) -> Vec<Bracket> {
+        bracketer::bracket(lexer::lex(input).unwrap().into_iter()).unwrap()
+    }
+
+    #[test]
+    fn should_compile_bracket_patterns() {
+        let rules = vec![ Rule::new(bracket_pattern("if (..) {..} else {..}").unwrap(), |_| "if else".to_string())
+                        , Rule::new(bracket_pattern("if (..) {..}").unwrap(), |_| "if".to_string())
+                        , Rule::with_match(bracket_pattern("$sym = $num ;").unwrap(), |m : Match<Bracket>|
+                            format!("{} {}", m.single("sym").unwrap().value(), m.single("num").unwrap().value()))
+                        , Rule::new(bracket_pattern("f ($sym, $any) ;").unwrap(), |_| "call".to_string())
+                        ];
+        let input = brackets("if (a) { b } else { c } if (d) { } x = 1; f(y, (z)); ");
+        let output = bracket::process_with(&rules, bracket::Strategy::Longest, input.into_iter()).unwrap();
+        assert_eq!(output, vec!["if else", "if", "x 1", "call"]);
+
+        let input = brackets("f(1, z);");
+        assert!(bracket::process(&rules, input.into_iter()).is_err());
+    }
+
+    #[test]
+    fn should_compile_lexeme_patterns() {
+        let dsl = Dsl::new().bind("upper", |x| matches!(x, Lexeme::Symbol(_, s) if s.starts_with(char::is_uppercase)));
+        let input = lexer::lex("1 . 2 $ Abc def").unwrap();
+
+        let pattern = [lexeme::Pattern::Seq(dsl.lexeme("$num . $num").unwrap())];
+        let output = lexeme::process_match(pattern, |m| m.get_all("num").concat(), input.clone().into_iter())
+                        .flatten()
+                        .map(|x| x.value())
+                        .collect::<Vec<_>>();
+        assert_eq!(output, vec!["1", "2", "$", "Abc", "def"]);
+
+        let pattern = [lexeme::Pattern::Seq(dsl.lexeme("$$ $upper $any").unwrap())];
+        let output = lexeme::process_match(pattern, |m| vec![m.single("upper").unwrap().clone()], input.into_iter())
+                        .flatten()
+                        .map(|x| x.value())
+                        .collect::<Vec<_>>();
+        assert_eq!(output, vec!["1", ".", "2", "Abc"]);
+    }
+
+    #[test]
+    fn should_match_touching_items_only_when_they_touch() {
+        let rules = vec![Rule::new(bracket_pattern("$sym == $num").unwrap(), |xs : Vec<Bracket>| xs[0].value())];
+        assert_eq!(bracket::process(&rules, brackets("x == 1").into_iter()).unwrap(), vec!["x"]);
+        assert!(bracket::process(&rules, brackets("x = = 1").into_iter()).is_err());
+
+        let pattern = [lexeme::Pattern::Seq(lexeme_pattern("$num.$num").unwrap())];
+        let output = lexeme::grouper(pattern, "float", lexer::lex("1.5 1 . 5").unwrap().into_iter()).collect::<Vec<_>>();
+        assert_eq!(output.len(), 4);
+        assert!(matches!(&output[0], Lexeme::Group(_, _, ls) if ls.len() == 3));
+    }
+
+    #[test]
+    fn should_report_dsl_errors() {
+        assert!(matches!(bracket_pattern("$nope"), Err(DslError::UnknownPlaceholder(m, n)) if m == LMeta::multi(0, 4) && n == "nope"));
+        assert!(matches!(lexeme_pattern("a $ (b"), Err(DslError::MissingPlaceholderName(m)) if m == LMeta::single(2)));
+        assert!(matches!(bracket_pattern("(a"), Err(DslError::Bracket(_))));
+        assert!(matches!(lexeme_pattern("\"a"), Err(DslError::Lex(_))));
+    }
+}
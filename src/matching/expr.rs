@@ -0,0 +1,174 @@
+
+use crate::data::{Bracket, Lexeme, LMeta};
+
+#[derive(Debug)]
+pub enum ExprError {
+    UnexpectedEnd(LMeta),
+    UnexpectedItem(Bracket),
+}
+
+impl ExprError {
+    pub fn meta(&self) -> LMeta {
+        match self {
+            ExprError::UnexpectedEnd(m) => m.clone(),
+            ExprError::UnexpectedItem(x) => x.meta(),
+        }
+    }
+}
+
+impl std::fmt::Display for ExprError {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ExprError::UnexpectedEnd(m) => write!(f, "Encountered end of expression while expecting an operand: {}", m.end),
+            ExprError::UnexpectedItem(x) => write!(f, "Encountered unexpected item in expression: {:?}", x),
+        }
+    }
+}
+
+impl std::error::Error for ExprError { }
+
+type Atom<T> = Box<dyn Fn(&Bracket) -> Option<T>>;
+type Unary<T> = Box<dyn Fn(Lexeme, T) -> T>;
+type Binary<T> = Box<dyn Fn(Lexeme, T, T) -> T>;
+
+/// Parses infix expressions over a sequence of brackets with binding powers.  Operators are found by
+/// `Lexeme::lmatch` against the `Bracket::Lex` items, `Bracket::Paren` groups a sub expression and
+/// everything else is handed to the atom function.
+pub struct ExprParser<T> {
+    atom : Atom<T>,
+    prefix : Vec<(Lexeme, u32, Unary<T>)>,
+    infix : Vec<(Lexeme, u32, u32, Binary<T>)>,
+    postfix : Vec<(Lexeme, u32, Unary<T>)>,
+}
+
+impl<T> ExprParser<T> {
+    pub fn new(atom : impl Fn(&Bracket) -> Option<T> + 'static) -> Self {
+        ExprParser { atom: Box::new(atom), prefix: vec![], infix: vec![], postfix: vec![] }
+    }
+    pub fn prefix(mut self, op : Lexeme, right : u32, f : impl Fn(Lexeme, T) -> T + 'static) -> Self {
+        self.prefix.push((op, right, Box::new(f)));
+        self
+    }
+    /// A left binding power lower than the right binding power makes the operator left associative
+    /// and a higher one makes it right associative.
+    pub fn infix(mut self, op : Lexeme, left : u32, right : u32, f : impl Fn(Lexeme, T, T) -> T + 'static) -> Self {
+        self.infix.push((op, left, right, Box::new(f)));
+        self
+    }
+    pub fn postfix(mut self, op : Lexeme, left : u32, f : impl Fn(Lexeme, T) -> T + 'static) -> Self {
+        self.postfix.push((op, left, Box::new(f)));
+        self
+    }
+
+    pub fn parse(&self, input : &[Bracket]) -> Result<T, ExprError> {
+        let end = input.last().map(|x| x.meta()).unwrap_or_default();
+        self.parse_all(input, end)
+    }
+
+    fn parse_all(&self, input : &[Bracket], end : LMeta) -> Result<T, ExprError> {
+        let mut index = 0;
+        let ret = self.expr(input, &mut index, 0, &end)?;
+        match input.get(index) {
+            Some(x) => Err(ExprError::UnexpectedItem(x.clone())),
+            None => Ok(ret),
+        }
+    }
+
+    fn expr(&self, input : &[Bracket], index : &mut usize, min : u32, end : &LMeta) -> Result<T, ExprError> {
+        let first = match input.get(*index) {
+            Some(x) => x,
+            None => { return Err(ExprError::UnexpectedEnd(end.clone())); },
+        };
+        *index += 1;
+
+        let mut ret = match first {
+            Bracket::Lex(l) if self.prefix.iter().any(|(op, _, _)| op.lmatch(l)) => {
+                let (_, right, f) = self.prefix.iter().find(|(op, _, _)| op.lmatch(l)).unwrap();
+                let operand = self.expr(input, index, *right, end)?;
+                f(l.clone(), operand)
+            },
+            Bracket::Paren(m, xs) => self.parse_all(xs, m.clone())?,
+            x => match (self.atom)(x) {
+                Some(atom) => atom,
+                None => { return Err(ExprError::UnexpectedItem(x.clone())); },
+            },
+        };
+
+        while let Some(next) = input.get(*index) {
+            let l = match next {
+                Bracket::Lex(l) => l,
+                x => { return Err(ExprError::UnexpectedItem(x.clone())); },
+            };
+
+            if let Some((_, left, f)) = self.postfix.iter().find(|(op, _, _)| op.lmatch(l)) {
+                if *left < min {
+                    break;
+                }
+                *index += 1;
+                ret = f(l.clone(), ret);
+            }
+            else if let Some((_, left, right, f)) = self.infix.iter().find(|(op, _, _, _)| op.lmatch(l)) {
+                if *left < min {
+                    break;
+                }
+                *index += 1;
+                let operand = self.expr(input, index, *right, end)?;
+                ret = f(l.clone(), ret, operand);
+            }
+            else {
+                return Err(ExprError::UnexpectedItem(next.clone()));
+            }
+        }
+
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parsing::lexer;
+    use crate::parsing::bracketer;
+    use crate::matching::lexeme::{punct, operator};
+
+    fn parser() -> ExprParser<String> {
+        fn binary(op : Lexeme, a : String, b : String) -> String { format!("({} {} {})", op.value(), a, b) }
+
+        ExprParser::new(|x| match x {
+                Bracket::Lex(l @ (Lexeme::Number(_, _) | Lexeme::Symbol(_, _))) => Some(l.value()),
+                _ => None,
+            })
+            .infix(punct('='), 2, 1, binary)
+            .infix(punct('+'), 3, 4, binary)
+            .infix(punct('-'), 3, 4, binary)
+            .infix(punct('*'), 5, 6, binary)
+            .infix(operator("**"), 8, 7, binary)
+            .prefix(punct('-'), 9, |op, a| format!("({} {})", op.value(), a))
+            .postfix(punct('!'), 11, |op, a| format!("({} {})", op.value(), a))
+    }
+
+    fn parse(input : &str) -> Result<String, ExprError> {
+        let config = lexer::LexerConfig::new().operators(vec!["**"]);
+        let tokens = config.lex(input).unwrap().into_iter();
+        let brackets = bracketer::bracket(tokens).unwrap();
+        parser().parse(&brackets)
+    }
+
+    #[test]
+    fn should_parse_with_precedence_and_associativity() {
+        assert_eq!(parse("a + b * (c - d)").unwrap(), "(+ a (* b (- c d)))");
+        assert_eq!(parse("1 - 2 - 3").unwrap(), "(- (- 1 2) 3)");
+        assert_eq!(parse("a = b = 2 ** 3 ** 4").unwrap(), "(= a (= b (** 2 (** 3 4))))");
+        assert_eq!(parse("-a * b! + -(c)!").unwrap(), "(+ (* (- a) (! b)) (- (! c)))");
+        assert_eq!(parse("((1))").unwrap(), "1");
+    }
+
+    #[test]
+    fn should_report_expression_errors() {
+        assert!(matches!(parse("1 +"), Err(ExprError::UnexpectedEnd(m)) if m == LMeta::single(2)));
+        assert!(matches!(parse("1 + ()"), Err(ExprError::UnexpectedEnd(m)) if m == LMeta::multi(4, 5)));
+        assert!(matches!(parse("1 2"), Err(ExprError::UnexpectedItem(Bracket::Lex(Lexeme::Number(_, _))))));
+        assert!(matches!(parse("1 + {}"), Err(ExprError::UnexpectedItem(Bracket::Curl(_, _)))));
+        assert!(matches!(parse(""), Err(ExprError::UnexpectedEnd(_))));
+    }
+}
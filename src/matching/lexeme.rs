@@ -1,71 +1,279 @@
 
 use crate::data::{LMeta, Lexeme};
+use super::{Match, Found, Predicate};
 
-pub struct LexProcessor<T, F, const N : usize> {
+/// The patterns that a `Matches` tries at each position of the input, each with its priority.
+type Prioritized = Vec<(Vec<Pattern>, i32)>;
+
+struct Matches<T> {
     input : T,
-    f : F,
-    pattern : [Pattern; N],
+    patterns : Prioritized,
     match_buffer : Vec<Lexeme>,
+    input_done : bool,
 }
 
-impl<T : Iterator<Item = Lexeme>, F : FnMut(Vec<Lexeme>) -> Vec<Lexeme>, const N : usize>
-    Iterator for LexProcessor<T, F, N> {
+enum Step {
+    Matched(usize, Match<Lexeme>),
+    Unmatched(Vec<Lexeme>),
+}
 
-    type Item = Vec<Lexeme>;
+impl<T : Iterator<Item = Lexeme>> Matches<T> {
+    fn new(patterns : Prioritized, input : T) -> Self {
+        Matches { input, patterns, match_buffer: vec![], input_done: false }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    fn step(&mut self) -> Option<Step> {
+        let empty = self.patterns.iter().all(|(p, _)| p.is_empty());
+        let max = self.patterns.iter().map(|(p, _)| p.iter().map(max_len).sum::<Option<usize>>()).try_fold(0, |max, len| Some(max.max(len?)));
         loop {
-            if self.pattern.len() == 0 {
+            if empty {
+                return self.input.next().map(|l| Step::Unmatched(vec![l]));
+            }
+            let (found, incomplete) = self.find(&self.match_buffer);
+            let matched = matches!(&found, Some((_, f)) if f.end > 0);
+            let short = !matched && max.is_some_and(|max| self.match_buffer.len() <= max);
+            if (incomplete || short || self.match_buffer.is_empty()) && !self.input_done {
                 match self.input.next() {
-                    None => { return None; },
-                    Some(l) => { return Some(vec![l]); },
+                    None => { self.input_done = true; },
+                    Some(l) => { self.match_buffer.push(l); },
                 }
+                continue;
             }
-            if self.match_buffer.len() == self.pattern.len() && pattern_match(&self.pattern, &self.match_buffer) {
-                return Some((self.f)(std::mem::replace(&mut self.match_buffer, vec![])));
-            }
-            match self.input.next() {
-                None if self.match_buffer.len() == 0 => { break None; },
-                None if self.match_buffer.len() < self.pattern.len() => { 
-                    return Some(std::mem::replace(&mut self.match_buffer, vec![]));
-                },
-                None if self.match_buffer.len() > self.pattern.len() => { 
-                    return Some(vec![self.match_buffer.remove(0)]);
-                },
-                None if pattern_match(&self.pattern, &self.match_buffer) => { 
-                    return Some((self.f)(std::mem::replace(&mut self.match_buffer, vec![])));
+            match found {
+                Some((index, Found { end, branches, captures })) if end > 0 => {
+                    let rest = self.match_buffer.split_off(end);
+                    let items = std::mem::replace(&mut self.match_buffer, rest);
+                    return Some(Step::Matched(index, Match { items, branches, captures }));
                 },
-                None => {
-                    return Some(std::mem::replace(&mut self.match_buffer, vec![]));
+                _ if self.match_buffer.is_empty() => { return None; },
+                _ if self.input_done && !self.matches_later() => {
+                    return Some(Step::Unmatched(std::mem::take(&mut self.match_buffer)));
                 },
-                Some(l) => {
-                    self.match_buffer.push(l);
-                    if self.match_buffer.len() > self.pattern.len() {
-                        return Some(vec![self.match_buffer.remove(0)]);
-                    }
-                    else if self.match_buffer.len() < self.pattern.len() {
-                        continue;
-                    }
-                    else if pattern_match(&self.pattern, &self.match_buffer) {
-                        return Some((self.f)(std::mem::replace(&mut self.match_buffer, vec![])));
-                    }
+                _ => { return Some(Step::Unmatched(vec![self.match_buffer.remove(0)])); },
+            }
+        }
+    }
+
+    /// Returns the match of the pattern with the longest match, then the highest priority and then the
+    /// earliest one, along with its index, and whether or not any pattern could continue matching if there
+    /// was more data.
+    fn find(&self, data : &[Lexeme]) -> (Option<(usize, Found)>, bool) {
+        let mut best : Option<(usize, Found)> = None;
+        let mut any_incomplete = false;
+        for (index, (pattern, priority)) in self.patterns.iter().enumerate() {
+            let (found, incomplete) = find_matches(pattern, data);
+            any_incomplete |= incomplete;
+            if let Some(f) = longest(found) {
+                let better = match &best {
+                    Some((b, bf)) => f.end > bf.end || (f.end == bf.end && *priority > self.patterns[*b].1),
+                    None => true,
+                };
+                if better {
+                    best = Some((index, f));
                 }
             }
         }
+        (best, any_incomplete)
+    }
+
+    fn matches_later(&self) -> bool {
+        (1..self.match_buffer.len()).any(|index| matches!(self.find(&self.match_buffer[index..]).0, Some((_, f)) if f.end > 0))
+    }
+}
+
+pub struct LexProcessor<T, F, const N : usize>(MatchProcessor<T, F, N>);
+
+impl<T : Iterator<Item = Lexeme>, F : FnMut(Vec<Lexeme>) -> Vec<Lexeme>, const N : usize>
+    Iterator for LexProcessor<T, F, N> {
+
+    type Item = Vec<Lexeme>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_with(|f, m| f(m.items))
     }
 }
 
-fn pattern_match(pattern : &[Pattern], data : &[Lexeme]) -> bool {
-    let pds = pattern.iter().zip(data.iter());
-    for pd in pds {
-        match pd {
-            (Pattern::Wild, _) => { },
-            (Pattern::Pred(f), d) if f(d) => { },
-            (Pattern::Exact(l), d) if l.lmatch(d) => { },
-            _ => { return false; },
+pub struct MatchProcessor<T, F, const N : usize> {
+    matches : Matches<T>,
+    f : F,
+}
+
+impl<T : Iterator<Item = Lexeme>, F, const N : usize> MatchProcessor<T, F, N> {
+    fn next_with(&mut self, apply : impl FnOnce(&mut F, Match<Lexeme>) -> Vec<Lexeme>) -> Option<Vec<Lexeme>> {
+        match self.matches.step()? {
+            Step::Matched(_, m) => Some(apply(&mut self.f, m)),
+            Step::Unmatched(ls) => Some(ls),
+        }
+    }
+}
+
+impl<T : Iterator<Item = Lexeme>, F : FnMut(Match<Lexeme>) -> Vec<Lexeme>, const N : usize>
+    Iterator for MatchProcessor<T, F, N> {
+
+    type Item = Vec<Lexeme>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_with(|f, m| f(m))
+    }
+}
+
+type LexAction<'a> = Box<dyn FnMut(Match<Lexeme>) -> Vec<Lexeme> + 'a>;
+
+/// A pattern and the action that replaces the items it matches, for use with `process_rules`.  The action
+/// can borrow from its environment for `'a`.
+pub struct LexRule<'a> {
+    pattern : Vec<Pattern>,
+    f : LexAction<'a>,
+    priority : i32,
+}
+
+impl<'a> LexRule<'a> {
+    pub fn new(pattern : Vec<Pattern>, mut f : impl FnMut(Vec<Lexeme>) -> Vec<Lexeme> + 'a) -> Self {
+        LexRule { pattern, f: Box::new(move |m| f(m.items)), priority: 0 }
+    }
+    pub fn with_match(pattern : Vec<Pattern>, f : impl FnMut(Match<Lexeme>) -> Vec<Lexeme> + 'a) -> Self {
+        LexRule { pattern, f: Box::new(f), priority: 0 }
+    }
+    /// Replaces the matched items with a `Group` with the label like `grouper` does.
+    pub fn group<S : AsRef<str>>(pattern : Vec<Pattern>, label : S) -> Self {
+        let label = label.as_ref().to_string();
+        LexRule::new(pattern, move |ls| vec![group_items(&label, ls)])
+    }
+    /// When several rules match the same number of items the one with the highest priority is used, and
+    /// after that the earliest one.  Defaults to 0.
+    pub fn priority(mut self, priority : i32) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+pub struct RuleProcessor<'a, T> {
+    matches : Matches<T>,
+    actions : Vec<LexAction<'a>>,
+    applied : usize,
+}
+
+impl<T : Iterator<Item = Lexeme>> Iterator for RuleProcessor<'_, T> {
+
+    type Item = Vec<Lexeme>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.matches.step()? {
+            Step::Matched(rule, m) => {
+                self.applied += 1;
+                Some((self.actions[rule])(m))
+            },
+            Step::Unmatched(ls) => Some(ls),
+        }
+    }
+}
+
+/// The most items that the pattern can match, or `None` when that is unbounded.
+fn max_len(pattern : &Pattern) -> Option<usize> {
+    match pattern {
+        Pattern::Seq(ps) => ps.iter().map(max_len).sum(),
+        Pattern::Alt(ps) => ps.iter().map(max_len).try_fold(0, |max, len| Some(max.max(len?))),
+        Pattern::Capture(_, p) | Pattern::Optional(p) => max_len(p),
+        Pattern::ZeroOrMore(p) | Pattern::OneOrMore(p) => max_len(p).filter(|len| *len == 0),
+        Pattern::Repeat(p, _, max) => max_len(p).map(|len| len * max),
+        Pattern::Adjacent | Pattern::Separated => Some(0),
+        _ => Some(1),
+    }
+}
+
+fn single_match(pattern : &Pattern, data : &Lexeme) -> bool {
+    match pattern {
+        Pattern::Wild => true,
+        Pattern::Pred(f) => f(data),
+        Pattern::Closure(f) => f.test(data),
+        Pattern::Exact(l) => l.lmatch(data),
+        Pattern::Label(label) => matches!(data, Lexeme::Group(_, l, _) if l == label),
+        _ => false,
+    }
+}
+
+/// Returns every prefix of the data that the pattern can match, along with the `Alt` branches and
+/// captures taken to get there, and whether or not the pattern could continue matching if there was more data.
+fn find_matches(pattern : &[Pattern], data : &[Lexeme]) -> (Vec<Found>, bool) {
+    let mut found = vec![];
+    let mut matcher = Matcher { data, incomplete: false, branches: vec![], captures: vec![] };
+    matcher.seq(pattern, 0, &mut |m, end| found.push(Found { end, branches: m.branches.clone(), captures: m.captures.clone() }));
+    (found, matcher.incomplete)
+}
+
+/// Picks the longest match, preferring earlier branches when two matches have the same length.
+fn longest(found : Vec<Found>) -> Option<Found> {
+    found.into_iter().fold(None, |ret, x| match ret {
+        Some(r) if r.end >= x.end => Some(r),
+        _ => Some(x),
+    })
+}
+
+struct Matcher<'a> {
+    data : &'a [Lexeme],
+    incomplete : bool,
+    branches : Vec<usize>,
+    captures : Vec<(String, std::ops::Range<usize>)>,
+}
+
+impl Matcher<'_> {
+    fn seq(&mut self, pattern : &[Pattern], index : usize, k : &mut dyn FnMut(&mut Self, usize)) {
+        match pattern.split_first() {
+            None => k(self, index),
+            Some((p, rest)) => self.one(p, index, &mut |m, next| m.seq(rest, next, k)),
+        }
+    }
+
+    fn one(&mut self, pattern : &Pattern, index : usize, k : &mut dyn FnMut(&mut Self, usize)) {
+        match pattern {
+            Pattern::Seq(ps) => self.seq(ps, index, k),
+            Pattern::Alt(ps) => {
+                for (branch, p) in ps.iter().enumerate() {
+                    self.branches.push(branch);
+                    self.one(p, index, k);
+                    self.branches.pop();
+                }
+            },
+            Pattern::Capture(name, p) => self.one(p, index, &mut |m, next| {
+                m.captures.push((name.clone(), index..next));
+                k(m, next);
+                m.captures.pop();
+            }),
+            Pattern::Optional(p) => self.repeat(p, 0, Some(1), 0, index, k),
+            Pattern::ZeroOrMore(p) => self.repeat(p, 0, None, 0, index, k),
+            Pattern::OneOrMore(p) => self.repeat(p, 1, None, 0, index, k),
+            Pattern::Repeat(p, min, max) => self.repeat(p, *min, Some(*max), 0, index, k),
+            _ if index >= self.data.len() => { self.incomplete = true; },
+            Pattern::Adjacent | Pattern::Separated if index == 0 => { },
+            Pattern::Adjacent | Pattern::Separated => {
+                let adjacent = self.data[index - 1].end_offset() == self.data[index].meta().start;
+                if adjacent == matches!(pattern, Pattern::Adjacent) {
+                    k(self, index);
+                }
+            },
+            p if single_match(p, &self.data[index]) => k(self, index + 1),
+            _ => { },
+        }
+    }
+
+    fn repeat( &mut self
+             , pattern : &Pattern
+             , min : usize
+             , max : Option<usize>
+             , count : usize
+             , index : usize
+             , k : &mut dyn FnMut(&mut Self, usize)
+             ) {
+        if count >= min {
+            k(self, index);
+        }
+        if max.is_none_or(|max| count < max) {
+            self.one(pattern, index, &mut |m, next| 
+                if next > index {
+                    m.repeat(pattern, min, max, count + 1, next, k);
+                });
         }
     }
-    true
 }
 
 #[derive(Debug)]
@@ -73,27 +281,125 @@ pub enum Pattern {
     Wild,
     Exact(Lexeme),
     Pred(fn(&Lexeme) -> bool),
+    Closure(Predicate<Lexeme>),
+    Seq(Vec<Pattern>),
+    /// Matches any one of the patterns.  Use `process_match` to find out which one matched.  The alternative
+    /// which matches the most items wins, so `Alt([Wild, Seq([Wild, Wild])])` splits `a b c` into two and one,
+    /// unlike `bracket::Pattern::Alt` under `Strategy::First`, which takes the earliest alternative.
+    Alt(Vec<Pattern>),
+    /// Names the items matched by the inner pattern so that they can be retrieved from the `Match`.
+    Capture(String, Box<Pattern>),
+    Optional(Box<Pattern>),
+    ZeroOrMore(Box<Pattern>),
+    OneOrMore(Box<Pattern>),
+    /// Matches the inner pattern at least `min` and at most `max` times.
+    Repeat(Box<Pattern>, usize, usize),
+    /// Matches no items when the next item starts right where the previous item ends.  Neither this
+    /// nor `Separated` matches at the start of the data.
+    Adjacent,
+    /// Matches no items when there is a gap, like whitespace or a comment, before the next item.
+    Separated,
+    /// Matches a `Group` with the label regardless of its contents.
+    Label(String),
 }
 
+/// Replaces each match of the pattern with a `Group` with the label.  Buffers the input like `process`.
 pub fn grouper<T : Iterator<Item = Lexeme>, S : AsRef<str>, const N : usize>(pattern : [Pattern; N], label : S, input : T) -> impl Iterator<Item = Lexeme> { 
 
     let label = label.as_ref().to_string();
 
-    let f = move |ls : Vec<Lexeme>| {
-        let start = ls.first().unwrap().meta().start;
-        let end = ls.last().unwrap().meta().end;
-        vec![Lexeme::Group(LMeta::multi(start, end), label.clone(), ls)]
-    };
+    let f = move |ls : Vec<Lexeme>| vec![group_items(&label, ls)];
 
-    LexProcessor { input, f, pattern, match_buffer: vec![] }.flatten()
+    LexProcessor(MatchProcessor::<T, _, N> { matches: Matches::new(vec![(Vec::from(pattern), 0)], input), f }).flatten()
 }
 
+fn group_items(label : &str, ls : Vec<Lexeme>) -> Lexeme {
+    let start = ls.first().unwrap().meta().start;
+    let end = ls.last().unwrap().meta().end;
+    Lexeme::Group(LMeta::multi(start, end), label.to_string(), ls)
+}
+
+/// Replaces each match of the pattern with the output of `f` and passes other items through.  The input is
+/// buffered for as long as the pattern could match more items, and the pattern is matched again from the
+/// start of the buffer for each new item, so a match of n items costs O(n²) and a trailing `ZeroOrMore(Wild)`
+/// buffers the rest of the input before anything is emitted.
 pub fn process< T : Iterator<Item = Lexeme>
               , F : FnMut(Vec<Lexeme>) -> Vec<Lexeme>
               , const N : usize
               >(pattern : [Pattern; N], f : F, input : T) -> LexProcessor<T, F, N> {
 
-    LexProcessor { input, f, pattern, match_buffer: vec![] }
+    LexProcessor(MatchProcessor { matches: Matches::new(vec![(Vec::from(pattern), 0)], input), f })
+}
+
+/// Like `process` except that `f` also receives the `Alt` branches and captures of the match.
+pub fn process_match< T : Iterator<Item = Lexeme>
+                    , F : FnMut(Match<Lexeme>) -> Vec<Lexeme>
+                    , const N : usize
+                    >(pattern : [Pattern; N], f : F, input : T) -> MatchProcessor<T, F, N> {
+
+    MatchProcessor { matches: Matches::new(vec![(Vec::from(pattern), 0)], input), f }
+}
+
+/// Applies all of the rules in a single pass over the input.  At each position the rule that matches the
+/// most items is applied, and items that no rule matches are passed through.  Buffers the input like `process`
+/// while any of the rules could match more items.
+pub fn process_rules<'a, T : Iterator<Item = Lexeme>>(rules : Vec<LexRule<'a>>, input : T) -> RuleProcessor<'a, T> {
+    let (patterns, actions) = rules.into_iter().map(|r| ((r.pattern, r.priority), r.f)).unzip();
+    RuleProcessor { matches: Matches::new(patterns, input), actions, applied: 0 }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FixpointError {
+    /// The rules were still changing the output after the maximum number of passes.
+    IterationLimit(LMeta, usize),
+}
+
+impl FixpointError {
+    pub fn meta(&self) -> LMeta {
+        match self {
+            FixpointError::IterationLimit(m, _) => m.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for FixpointError {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FixpointError::IterationLimit(m, limit) => write!(f, "Rules did not settle after {} passes: {}", limit, m.start),
+        }
+    }
+}
+
+impl std::error::Error for FixpointError { }
+
+/// Applies the rules with `process_rules` again to its own output until a pass leaves it unchanged, so that
+/// later passes can match the groups made by earlier ones with `Pattern::Label`.  Only the passes that change
+/// the output count towards `limit`, and rules which are still changing the output after `limit` of them, like
+/// ones that regroup a group, are an `IterationLimit` error.
+pub fn process_fixpoint<T : Iterator<Item = Lexeme>>(rules : Vec<LexRule<'_>>, limit : usize, input : T) -> Result<Vec<Lexeme>, FixpointError> {
+    let mut items = input.collect::<Vec<_>>();
+    let mut rules = rules;
+    let mut changes = 0;
+    loop {
+        let mut processor = process_rules(rules, items.clone().into_iter());
+        let output = processor.by_ref().flatten().collect::<Vec<_>>();
+        if processor.applied == 0 || output == items {
+            return Ok(output);
+        }
+        if changes == limit {
+            break;
+        }
+        changes += 1;
+        rules = processor.matches.patterns.into_iter().zip(processor.actions)
+                         .map(|((pattern, priority), f)| LexRule { pattern, f, priority })
+                         .collect();
+        items = output;
+    }
+    let meta = match (items.first(), items.last()) {
+        (Some(first), Some(last)) => LMeta::multi(first.meta().start, last.meta().end),
+        _ => LMeta::new(),
+    };
+    Err(FixpointError::IterationLimit(meta, limit))
 }
 
 pub fn r_paren() -> Lexeme { Lexeme::RParen(LMeta::new()) } 
@@ -105,11 +411,38 @@ pub fn l_curl() -> Lexeme { Lexeme::LCurl(LMeta::new()) }
 pub fn r_square() -> Lexeme { Lexeme::RSquare(LMeta::new()) } 
 pub fn l_square() -> Lexeme { Lexeme::LSquare(LMeta::new()) } 
 pub fn punct(c : char) -> Lexeme { Lexeme::Punct(LMeta::new(), c) }
+pub fn operator<S : AsRef<str>>(s : S) -> Lexeme { Lexeme::Operator(LMeta::new(), s.as_ref().to_string()) }
 pub fn group<S : AsRef<str>>(label : S, ls : Vec<Lexeme>) -> Lexeme { Lexeme::Group(LMeta::new(), label.as_ref().to_string(), ls) }
-pub fn string<S : AsRef<str>>(s : S) -> Lexeme { Lexeme::String(LMeta::new(), s.as_ref().to_string()) }
+pub fn string<S : AsRef<str>>(s : S) -> Lexeme { Lexeme::String(LMeta::new(), s.as_ref().to_string(), crate::parsing::unparser::UnparseConfig::default().escape(s.as_ref())) }
 pub fn number<S : AsRef<str>>(s : S) -> Lexeme { Lexeme::Number(LMeta::new(), s.as_ref().to_string()) }
 pub fn symbol<S : AsRef<str>>(s : S) -> Lexeme { Lexeme::Symbol(LMeta::new(), s.as_ref().to_string()) }
 
+/// The lexemes that the text lexes as with the default lexer, so `"=="` is two `=` puncts and `"1.5"` is
+/// a number, a `.` punct and a number.
+pub fn literal<S : AsRef<str>>(s : S) -> Vec<Lexeme> {
+    crate::parsing::lexer::lex_recover(s.as_ref()).0
+}
+
+/// Matches the lexemes of `literal`, which have to be next to each other when they are next to each
+/// other in the text.  Input lexed with operators or number literals needs `Pattern::Exact` instead.
+pub fn literal_pattern<S : AsRef<str>>(s : S) -> Pattern {
+    let mut ls = literal(s);
+    if ls.len() == 1 {
+        return Pattern::Exact(ls.remove(0));
+    }
+    let mut ret = vec![];
+    for (index, l) in ls.iter().enumerate() {
+        if index > 0 && ls[index - 1].end_offset() == l.meta().start {
+            ret.push(Pattern::Adjacent);
+        }
+        else if index > 0 {
+            ret.push(Pattern::Separated);
+        }
+        ret.push(Pattern::Exact(l.clone()));
+    }
+    Pattern::Seq(ret)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -117,6 +450,7 @@ mod test {
     use crate::parsing::lexer;
 
     #[test]
+    #[allow(clippy::needless_borrow)]
     fn should_process() {
         let input = "1 2 3";
         let tokens = lexer::lex(&input).unwrap();
@@ -150,6 +484,18 @@ mod test {
     }
 
     #[test]
+    fn should_pass_unmatched_items_at_the_end_through_together() {
+        let pattern = || [Pattern::Exact(symbol("a")), Pattern::Exact(symbol("b"))];
+        let output = process(pattern(), |ls| ls, lexer::lex("x y a b z a").unwrap().into_iter()).collect::<Vec<_>>();
+        assert_eq!(output.iter().map(|ls| ls.len()).collect::<Vec<_>>(), vec![1, 1, 2, 2]);
+
+        let pattern = [Pattern::Exact(symbol("a")), Pattern::ZeroOrMore(Box::new(Pattern::Exact(symbol("b"))))];
+        let output = process(pattern, |ls| vec![group("ab", ls)], lexer::lex("x a b b y").unwrap().into_iter()).collect::<Vec<_>>();
+        assert_eq!(output.iter().map(|ls| ls.len()).collect::<Vec<_>>(), vec![1, 1, 1]);
+    }
+
+    #[test]
+    #[allow(clippy::needless_borrow)]
     fn should_group_with_zero_length_input() {
         let input = "";
         let tokens = lexer::lex(&input).unwrap();
@@ -158,6 +504,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::needless_borrow)]
     fn should_group_with_zero_length_pattern() {
         let input = "1 2 3";
         let tokens = lexer::lex(&input).unwrap();
@@ -169,6 +516,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::needless_borrow)]
     fn should_group_with_wild_pattern() {
         let input = "1 2 3";
         let tokens = lexer::lex(&input).unwrap();
@@ -190,6 +538,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::needless_borrow)]
     fn should_group_with_exact_pattern() {
         let input = "1 2 3 4";
         let tokens = lexer::lex(&input).unwrap();
@@ -216,6 +565,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::needless_borrow)]
     fn should_group_with_pred_pattern() {
         fn odd(l : &Lexeme) -> bool {
             match l {
@@ -244,6 +594,7 @@ mod test {
     }
     
     #[test]
+    #[allow(clippy::needless_borrow)]
     fn should_group_float_like_structure() {
         fn any_num() -> Pattern {
             Pattern::Pred(|x| matches!(x, Lexeme::Number(_, _)))
@@ -297,4 +648,275 @@ mod test {
             assert!(matches!(ls[2], Lexeme::Number(_, _)));
         }
     }
+
+    #[test]
+    fn should_group_dotted_paths_with_zero_or_more() {
+        fn any_sym() -> Pattern {
+            Pattern::Pred(|x| matches!(x, Lexeme::Symbol(_, _)))
+        }
+
+        let input = "a.b.c.d x e.f g.";
+        let tokens = lexer::lex(input).unwrap().into_iter();
+        let pattern = [any_sym(), Pattern::ZeroOrMore(Box::new(Pattern::Seq(vec![Pattern::Exact(punct('.')), any_sym()])))];
+        let output = grouper(pattern, "path", tokens).collect::<Vec<_>>();
+        assert_eq!(output.len(), 5);
+        assert!(matches!(&output[0], Lexeme::Group(m, _, ls) if *m == LMeta::multi(0, 6) && ls.len() == 7));
+        assert!(matches!(&output[1], Lexeme::Group(m, _, ls) if *m == LMeta::single(8) && ls.len() == 1));
+        assert!(matches!(&output[2], Lexeme::Group(m, _, ls) if *m == LMeta::multi(10, 12) && ls.len() == 3));
+        assert!(matches!(&output[3], Lexeme::Group(m, _, ls) if *m == LMeta::single(14) && ls.len() == 1));
+        assert!(matches!(output[4], Lexeme::Punct(_, '.')));
+    }
+
+    #[test]
+    fn should_group_argument_lists_with_one_or_more_and_optional() {
+        fn any_num() -> Pattern {
+            Pattern::Pred(|x| matches!(x, Lexeme::Number(_, _)))
+        }
+
+        let input = "(1, 2, 3,) () (4) (5 6)";
+        let tokens = lexer::lex(input).unwrap().into_iter();
+        let pattern = [ Pattern::Exact(l_paren())
+                      , any_num()
+                      , Pattern::ZeroOrMore(Box::new(Pattern::Seq(vec![Pattern::Exact(punct(',')), any_num()])))
+                      , Pattern::Optional(Box::new(Pattern::Exact(punct(','))))
+                      , Pattern::Exact(r_paren())
+                      ];
+        let output = grouper(pattern, "args", tokens).collect::<Vec<_>>();
+        assert_eq!(output.len(), 8);
+        assert!(matches!(&output[0], Lexeme::Group(_, _, ls) if ls.len() == 8));
+        assert!(matches!(output[1], Lexeme::LParen(_)));
+        assert!(matches!(output[2], Lexeme::RParen(_)));
+        assert!(matches!(&output[3], Lexeme::Group(_, _, ls) if ls.len() == 3));
+        assert!(matches!(output[4], Lexeme::LParen(_)));
+        assert!(matches!(output[5], Lexeme::Number(_, _)));
+        assert!(matches!(output[6], Lexeme::Number(_, _)));
+        assert!(matches!(output[7], Lexeme::RParen(_)));
+    }
+
+    #[test]
+    fn should_group_with_bounded_repeat() {
+        let input = "1 2 3 4 5 x 6";
+        let tokens = lexer::lex(input).unwrap().into_iter();
+        let pattern = [Pattern::Repeat(Box::new(Pattern::Pred(|x| matches!(x, Lexeme::Number(_, _)))), 2, 3)];
+        let output = grouper(pattern, "nums", tokens).collect::<Vec<_>>();
+        assert_eq!(output.len(), 4);
+        assert!(matches!(&output[0], Lexeme::Group(_, _, ls) if ls.len() == 3));
+        assert!(matches!(&output[1], Lexeme::Group(_, _, ls) if ls.len() == 2));
+        assert!(matches!(output[2], Lexeme::Symbol(_, _)));
+        assert!(matches!(output[3], Lexeme::Number(_, _)));
+    }
+
+    #[test]
+    fn should_report_alt_branches() {
+        let input = "1 + 2 3 - 4 * 5 - = 6";
+        let tokens = lexer::lex(input).unwrap().into_iter();
+        let pattern = [ Pattern::Pred(|x| matches!(x, Lexeme::Number(_, _)))
+                      , Pattern::Alt(vec![ Pattern::Exact(punct('+'))
+                                         , Pattern::Seq(vec![Pattern::Exact(punct('-')), Pattern::Exact(punct('='))])
+                                         , Pattern::Exact(punct('-'))
+                                         ])
+                      , Pattern::Wild
+                      ];
+        let output = process_match(pattern, |m| vec![group(m.branches.iter().map(|b| b.to_string()).collect::<String>(), m.items)], tokens)
+                        .flatten()
+                        .collect::<Vec<_>>();
+        assert_eq!(output.len(), 4);
+        assert!(matches!(&output[0], Lexeme::Group(_, label, ls) if label == "0" && ls.len() == 3));
+        assert!(matches!(&output[1], Lexeme::Group(_, label, ls) if label == "2" && ls.len() == 3));
+        assert!(matches!(output[2], Lexeme::Punct(_, '*')));
+        assert!(matches!(&output[3], Lexeme::Group(_, label, ls) if label == "1" && ls.len() == 4));
+    }
+
+    #[test]
+    fn should_take_longest_alt() {
+        let pattern = [Pattern::Alt(vec![Pattern::Wild, Pattern::Seq(vec![Pattern::Wild, Pattern::Wild])])];
+        let output = grouper(pattern, "x", lexer::lex("a b c").unwrap().into_iter()).collect::<Vec<_>>();
+        let lens = output.iter().map(|x| match x { Lexeme::Group(_, _, ls) => ls.len(), _ => 0 }).collect::<Vec<_>>();
+        assert_eq!(lens, vec![2, 1]);
+    }
+
+    #[test]
+    fn should_capture_by_name() {
+        fn any_sym() -> Pattern {
+            Pattern::Pred(|x| matches!(x, Lexeme::Symbol(_, _)))
+        }
+
+        let input = "a.b.c = 1";
+        let tokens = lexer::lex(input).unwrap().into_iter();
+        let pattern = [ Pattern::Capture("path".into(), Box::new(Pattern::Seq(vec![ Pattern::Capture("segment".into(), Box::new(any_sym()))
+                                                                                 , Pattern::ZeroOrMore(Box::new(Pattern::Seq(vec![ Pattern::Exact(punct('.'))
+                                                                                                                                  , Pattern::Capture("segment".into(), Box::new(any_sym()))
+                                                                                                                                  ])))
+                                                                                 ])))
+                      , Pattern::Exact(punct('='))
+                      , Pattern::Capture("value".into(), Box::new(Pattern::Wild))
+                      ];
+        let output = process_match(pattern, |m| {
+            assert_eq!(m.get("path").unwrap().len(), 5);
+            assert_eq!(m.get_all("segment").iter().map(|s| s[0].value()).collect::<Vec<_>>(), vec!["a", "b", "c"]);
+            assert_eq!(m.single("segment").unwrap().value(), "a");
+            assert!(m.single("path").is_none());
+            assert!(m.get("missing").is_none());
+            vec![m.single("value").unwrap().clone()]
+        }, tokens).flatten().collect::<Vec<_>>();
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].value(), "1");
+    }
+
+    #[test]
+    fn should_group_with_closure_pattern() {
+        use crate::matching::Predicate;
+
+        let keywords = ["let", "fn"].iter().map(|k| k.to_string()).collect::<std::collections::HashSet<_>>();
+        let keyword = Pattern::Closure(Predicate::new(move |x| matches!(x, Lexeme::Symbol(_, s) if keywords.contains(s))));
+
+        let input = "let x fn y z";
+        let tokens = lexer::lex(input).unwrap().into_iter();
+        let output = grouper([keyword, Pattern::Wild], "decl", tokens).collect::<Vec<_>>();
+        assert_eq!(output.len(), 3);
+        assert!(matches!(&output[0], Lexeme::Group(_, _, ls) if ls[1].value() == "x"));
+        assert!(matches!(&output[1], Lexeme::Group(_, _, ls) if ls[1].value() == "y"));
+        assert!(matches!(output[2], Lexeme::Symbol(_, _)));
+    }
+
+    #[test]
+    fn should_group_only_adjacent_items() {
+        fn any_num() -> Pattern {
+            Pattern::Pred(|x| matches!(x, Lexeme::Number(_, _)))
+        }
+
+        let input = "1.2 + 3 . 4 + 5. 6";
+        let tokens = lexer::lex(input).unwrap().into_iter();
+        let pattern = [any_num(), Pattern::Adjacent, Pattern::Exact(punct('.')), Pattern::Adjacent, any_num()];
+        let output = grouper(pattern, "float", tokens).collect::<Vec<_>>();
+        assert_eq!(output.len(), 9);
+        assert!(matches!(&output[0], Lexeme::Group(m, _, ls) if ls.len() == 3 && *m == LMeta::multi(0, 2)));
+        assert!(output[1..].iter().all(|x| !matches!(x, Lexeme::Group(_, _, _))));
+
+        let input = "x++ + y + +z";
+        let tokens = lexer::lex(input).unwrap().into_iter();
+        let pattern = [Pattern::Exact(punct('+')), Pattern::Adjacent, Pattern::Exact(punct('+'))];
+        let output = grouper(pattern, "increment", tokens).collect::<Vec<_>>();
+        assert_eq!(output.len(), 7);
+        assert!(matches!(&output[1], Lexeme::Group(m, _, _) if *m == LMeta::multi(1, 2)));
+
+        let input = "a b.c";
+        let tokens = lexer::lex(input).unwrap().into_iter();
+        let output = grouper([Pattern::Wild, Pattern::Separated, Pattern::Wild], "spaced", tokens).collect::<Vec<_>>();
+        assert_eq!(output.len(), 3);
+        assert!(matches!(&output[0], Lexeme::Group(_, _, ls) if ls.len() == 2));
+        assert!(matches!(output[1], Lexeme::Punct(_, '.')));
+        assert!(matches!(output[2], Lexeme::Symbol(_, _)));
+    }
+
+    #[test]
+    fn should_process_several_rules_in_one_pass() {
+        fn any_num() -> Pattern { Pattern::Pred(|x| matches!(x, Lexeme::Number(_, _))) }
+        fn any_sym() -> Pattern { Pattern::Pred(|x| matches!(x, Lexeme::Symbol(_, _))) }
+
+        let rules = || vec![ LexRule::group(vec![any_num(), Pattern::Exact(punct('.')), any_num()], "float")
+                           , LexRule::group(vec![any_sym(), Pattern::OneOrMore(Box::new(Pattern::Seq(vec![Pattern::Exact(punct('.')), any_sym()])))], "path")
+                           , LexRule::group(vec![Pattern::Exact(punct('+')), Pattern::Exact(punct('+'))], "increment")
+                           , LexRule::new(vec![Pattern::Exact(punct(';'))], |_| vec![])
+                           ];
+
+        let input = "a.b.c = 1.2; x++ + 3";
+        let tokens = lexer::lex(input).unwrap().into_iter();
+        let output = process_rules(rules(), tokens).flatten().collect::<Vec<_>>();
+        let labels = output.iter().map(|x| match x {
+            Lexeme::Group(_, label, _) => label.clone(),
+            x => x.value(),
+        }).collect::<Vec<_>>();
+        assert_eq!(labels, vec!["path", "=", "float", "x", "increment", "+", "3"]);
+        assert!(matches!(&output[0], Lexeme::Group(m, _, ls) if ls.len() == 5 && *m == LMeta::multi(0, 4)));
+
+        let output = process_rules(vec![], lexer::lex("a b").unwrap().into_iter()).flatten().collect::<Vec<_>>();
+        assert_eq!(output.len(), 2);
+    }
+
+    #[test]
+    fn should_process_rules_with_borrowing_actions() {
+        let mut semis = vec![];
+        let mut nums = 0;
+        {
+            let rules = vec![ LexRule::new(vec![Pattern::Exact(punct(';'))], |ls| { semis.extend(ls); vec![] })
+                            , LexRule::new(vec![Pattern::Pred(|x| matches!(x, Lexeme::Number(_, _)))], |ls| { nums += 1; ls })
+                            ];
+            let output = process_rules(rules, lexer::lex("a; 1; 2").unwrap().into_iter()).flatten().collect::<Vec<_>>();
+            assert_eq!(output.len(), 3);
+        }
+        assert_eq!(semis.len(), 2);
+        assert_eq!(nums, 2);
+    }
+
+    #[test]
+    fn should_prefer_longest_then_priority_then_order() {
+        let plus = || Pattern::Exact(punct('+'));
+        let label = |output : Vec<Lexeme>| match &output[0] {
+            Lexeme::Group(_, label, ls) => format!("{}:{}", label, ls.len()),
+            x => x.value(),
+        };
+
+        let rules = vec![ LexRule::group(vec![plus()], "one")
+                        , LexRule::group(vec![plus(), plus()], "two")
+                        ];
+        let output = process_rules(rules, lexer::lex("++").unwrap().into_iter()).flatten().collect::<Vec<_>>();
+        assert_eq!(label(output), "two:2");
+
+        let rules = vec![ LexRule::group(vec![plus()], "first")
+                        , LexRule::group(vec![Pattern::Wild], "second")
+                        ];
+        let output = process_rules(rules, lexer::lex("+").unwrap().into_iter()).flatten().collect::<Vec<_>>();
+        assert_eq!(label(output), "first:1");
+
+        let rules = vec![ LexRule::group(vec![plus()], "first")
+                        , LexRule::group(vec![Pattern::Wild], "second").priority(1)
+                        ];
+        let output = process_rules(rules, lexer::lex("+").unwrap().into_iter()).flatten().collect::<Vec<_>>();
+        assert_eq!(label(output), "second:1");
+
+        let rules = vec![ LexRule::with_match(vec![Pattern::Capture("x".into(), Box::new(Pattern::Wild)), plus()], |m| {
+                              vec![m.single("x").unwrap().clone()]
+                          })
+                        ];
+        let output = process_rules(rules, lexer::lex("a + b").unwrap().into_iter()).flatten().collect::<Vec<_>>();
+        assert_eq!(output.iter().map(|x| x.value()).collect::<Vec<_>>(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn should_process_to_fixpoint() {
+        fn any_sym() -> Pattern { Pattern::Pred(|x| matches!(x, Lexeme::Symbol(_, _))) }
+        fn path() -> Pattern { Pattern::Label("path".into()) }
+
+        let rules = || vec![ LexRule::group(vec![any_sym(), Pattern::Exact(punct('.')), any_sym()], "path")
+                           , LexRule::group(vec![path(), Pattern::Exact(punct('.')), any_sym()], "path")
+                           , LexRule::group(vec![path(), Pattern::Exact(punct('=')), Pattern::Wild], "assign")
+                           ];
+
+        let input = "a.b.c = 1";
+        let output = process_fixpoint(rules(), 10, lexer::lex(input).unwrap().into_iter()).unwrap();
+        assert_eq!(output.len(), 1);
+        assert!(matches!(&output[0], Lexeme::Group(m, label, ls) if label == "assign" && ls.len() == 3 && *m == LMeta::multi(0, 8)));
+        if let Lexeme::Group(_, _, ls) = &output[0] {
+            assert!(matches!(&ls[0], Lexeme::Group(_, label, inner) if label == "path" && inner.len() == 3));
+        }
+
+        let output = process_fixpoint(rules(), 1, lexer::lex("x y").unwrap().into_iter()).unwrap();
+        assert_eq!(output.len(), 2);
+
+        let output = process_fixpoint(rules(), 3, lexer::lex(input).unwrap().into_iter()).unwrap();
+        assert_eq!(output.len(), 1);
+
+        let error = process_fixpoint(rules(), 2, lexer::lex(input).unwrap().into_iter()).unwrap_err();
+        assert_eq!(error, FixpointError::IterationLimit(LMeta::multi(0, 8), 2));
+
+        let output = process_fixpoint(rules(), 0, lexer::lex("x y").unwrap().into_iter()).unwrap();
+        assert_eq!(output.len(), 2);
+        let output = process_fixpoint(rules(), 0, std::iter::empty()).unwrap();
+        assert!(output.is_empty());
+
+        let wrap = vec![LexRule::group(vec![Pattern::Wild], "wrap")];
+        let error = process_fixpoint(wrap, 5, lexer::lex("x").unwrap().into_iter()).unwrap_err();
+        assert_eq!(error, FixpointError::IterationLimit(LMeta::single(0), 5));
+    }
 }
@@ -1,3 +1,60 @@
-
 pub mod lexeme;
-pub mod bracket;
\ No newline at end of file
+pub mod bracket;
+pub mod expr;
+pub mod split;
+pub mod peg;
+pub mod combinator;
+pub mod dsl;
+
+use std::ops::Range;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match<T> {
+    pub items : Vec<T>,
+    /// The index of the alternative taken at each `Alt` pattern in the order that they were matched.
+    pub branches : Vec<usize>,
+    /// The name and item range of each `Capture` pattern in the order that they were matched.
+    pub captures : Vec<(String, Range<usize>)>,
+}
+
+impl<T> Match<T> {
+    /// Returns the items of the first capture with the given name.
+    pub fn get(&self, name : &str) -> Option<&[T]> {
+        self.captures.iter().find(|(n, _)| n == name).map(|(_, r)| &self.items[r.clone()])
+    }
+    /// Returns the items of every capture with the given name, which is useful for captures inside of repetitions.
+    pub fn get_all(&self, name : &str) -> Vec<&[T]> {
+        self.captures.iter().filter(|(n, _)| n == name).map(|(_, r)| &self.items[r.clone()]).collect()
+    }
+    /// Returns the item of the first capture with the given name when that capture holds exactly one item.
+    pub fn single(&self, name : &str) -> Option<&T> {
+        match self.get(name) {
+            Some([x]) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+/// A predicate that can capture its environment, for use with `Pattern::Closure`.
+pub struct Predicate<T>(Box<dyn Fn(&T) -> bool>);
+
+impl<T> Predicate<T> {
+    pub fn new(f : impl Fn(&T) -> bool + 'static) -> Self {
+        Predicate(Box::new(f))
+    }
+    pub fn test(&self, item : &T) -> bool {
+        (self.0)(item)
+    }
+}
+
+impl<T> std::fmt::Debug for Predicate<T> {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Predicate")
+    }
+}
+
+pub(crate) struct Found {
+    end : usize,
+    branches : Vec<usize>,
+    captures : Vec<(String, Range<usize>)>,
+}
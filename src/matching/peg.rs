@@ -0,0 +1,478 @@
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use crate::data::{Bracket, LMeta};
+use super::Match;
+use super::bracket::{Pattern, prefix_match};
+
+/// Parsing expressions over brackets.  Unlike `bracket::process` every choice is ordered and a failed
+/// alternative backtracks to where it started.
+#[derive(Debug)]
+pub enum Peg {
+    /// Matches the pattern against the items at the current position and takes the first way that it
+    /// matches.  `Pattern::Parse` is matched as its inner pattern, use `Peg::Parse` instead.
+    Item(Pattern),
+    /// Parses with the named rule at the current position.
+    Rule(String),
+    /// Matches one item with the pattern and then parses all of its contents with the named rule.
+    Parse(String, Pattern),
+    Seq(Vec<Peg>),
+    /// Takes the first alternative that matches.
+    Choice(Vec<Peg>),
+    Optional(Box<Peg>),
+    ZeroOrMore(Box<Peg>),
+    OneOrMore(Box<Peg>),
+    /// Succeeds without consuming anything when the inner expression does not match.
+    Not(Box<Peg>),
+    /// Succeeds without consuming anything when the inner expression matches.
+    And(Box<Peg>),
+}
+
+impl Peg {
+    fn rule_names<'a>(&'a self, output : &mut Vec<&'a str>) {
+        match self {
+            Peg::Item(_) => { },
+            Peg::Rule(name) | Peg::Parse(name, _) => { output.push(name); },
+            Peg::Seq(ps) | Peg::Choice(ps) => { ps.iter().for_each(|p| p.rule_names(output)); },
+            Peg::Optional(p) | Peg::ZeroOrMore(p) | Peg::OneOrMore(p) | Peg::Not(p) | Peg::And(p) => { p.rule_names(output); },
+        }
+    }
+}
+
+type PegAction<T> = Box<dyn FnMut(Match<Bracket>, Vec<T>) -> T>;
+
+/// One alternative of a named rule.  The action receives the matched items and the results of
+/// every `Peg::Rule` and `Peg::Parse` in the order that they were matched.
+pub struct PegRule<T> {
+    peg : Peg,
+    f : RefCell<PegAction<T>>,
+}
+
+impl<T> PegRule<T> {
+    pub fn new(peg : Peg, f : impl FnMut(Match<Bracket>, Vec<T>) -> T + 'static) -> Self {
+        PegRule { peg, f: RefCell::new(Box::new(f)) }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PegError {
+    UnknownRule(String),
+    /// The location where parsing got furthest and the descriptions of what could have matched there.
+    Expected(LMeta, Vec<String>),
+}
+
+impl PegError {
+    pub fn meta(&self) -> LMeta {
+        match self {
+            PegError::UnknownRule(_) => LMeta::new(),
+            PegError::Expected(m, _) => m.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for PegError {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PegError::UnknownRule(name) => write!(f, "The grammar does not have a rule named: {}", name),
+            PegError::Expected(m, expected) => write!(f, "Expected {} at: {}", expected.join(" or "), m.start),
+        }
+    }
+}
+
+impl std::error::Error for PegError { }
+
+/// Named rules whose alternatives are tried in order.  Rules which are left recursive fail instead of
+/// looping forever.
+pub struct PegGrammar<T> {
+    rules : Vec<(String, Vec<PegRule<T>>)>,
+    memoize : bool,
+}
+
+impl<T> Default for PegGrammar<T> {
+    fn default() -> Self {
+        PegGrammar { rules: vec![], memoize: false }
+    }
+}
+
+impl<T> PegGrammar<T> {
+    pub fn new() -> Self {
+        PegGrammar::default()
+    }
+    pub fn rule<S : AsRef<str>>(mut self, name : S, alternatives : Vec<PegRule<T>>) -> Self {
+        self.rules.push((name.as_ref().to_string(), alternatives));
+        self
+    }
+    /// Remembers the result of each rule at each position so that backtracking never parses the
+    /// same items with the same rule twice.  Actions then only run once for each position.
+    pub fn memoize(mut self, enable : bool) -> Self {
+        self.memoize = enable;
+        self
+    }
+}
+
+impl<T : Clone> PegGrammar<T> {
+    /// Parses all of the input with the named rule.
+    pub fn parse(&self, name : &str, input : &[Bracket]) -> Result<T, PegError> {
+        let mut names = vec![name];
+        for (_, alternatives) in &self.rules {
+            alternatives.iter().for_each(|a| a.peg.rule_names(&mut names));
+        }
+        if let Some(missing) = names.into_iter().find(|n| self.index(n).is_none()) {
+            return Err(PegError::UnknownRule(missing.to_string()));
+        }
+
+        let end = input.last().map(|x| LMeta::single(x.meta().end)).unwrap_or_default();
+        let input = Input { id: 0, items: input, end };
+        let mut parser = Parser { grammar: self, memo: HashMap::new(), active: HashSet::new(), nested: HashMap::new(), furthest: None };
+        match parser.all(name, &input, "end of input") {
+            Some(ret) => Ok(ret),
+            None => {
+                let (meta, expected) = parser.furthest.unwrap_or((input.end, vec![]));
+                Err(PegError::Expected(meta, expected))
+            },
+        }
+    }
+
+    fn index(&self, name : &str) -> Option<usize> {
+        self.rules.iter().position(|(n, _)| n == name)
+    }
+}
+
+struct Input<'a> {
+    /// Tells apart the top level items and the contents of each item parsed with `Peg::Parse`.
+    id : usize,
+    items : &'a [Bracket],
+    /// Where errors at the end of the items are reported.
+    end : LMeta,
+}
+
+/// The rule, the id and length of the input and the position in the items.
+type Key = (usize, usize, usize, usize);
+
+struct Parser<'g, T> {
+    grammar : &'g PegGrammar<T>,
+    memo : HashMap<Key, Option<(usize, T)>>,
+    active : HashSet<Key>,
+    /// The id of the contents of the item at each position of each input.
+    nested : HashMap<(usize, usize), usize>,
+    furthest : Option<(LMeta, Vec<String>)>,
+}
+
+/// The results, branches and captures collected by an alternative so far.
+struct Collected<T> {
+    values : Vec<T>,
+    branches : Vec<usize>,
+    captures : Vec<(String, std::ops::Range<usize>)>,
+}
+
+impl<T> Collected<T> {
+    fn mark(&self) -> (usize, usize, usize) {
+        (self.values.len(), self.branches.len(), self.captures.len())
+    }
+    fn reset(&mut self, (values, branches, captures) : (usize, usize, usize)) {
+        self.values.truncate(values);
+        self.branches.truncate(branches);
+        self.captures.truncate(captures);
+    }
+}
+
+impl<T : Clone> Parser<'_, T> {
+    fn expect(&mut self, input : &Input, index : usize, expected : Vec<String>) {
+        let meta = input.items.get(index).map(|x| x.meta()).unwrap_or_else(|| input.end.clone());
+        match &mut self.furthest {
+            Some((m, _)) if m.start > meta.start => { },
+            Some((m, es)) if m.start == meta.start => {
+                for e in expected {
+                    if !es.contains(&e) {
+                        es.push(e);
+                    }
+                }
+            },
+            _ => { self.furthest = Some((meta, expected)); },
+        }
+    }
+
+    fn all(&mut self, name : &str, input : &Input, end : &str) -> Option<T> {
+        match self.rule(name, input, 0) {
+            Some((index, ret)) if index == input.items.len() => Some(ret),
+            Some((index, _)) => {
+                self.expect(input, index, vec![end.to_string()]);
+                None
+            },
+            None => None,
+        }
+    }
+
+    fn rule(&mut self, name : &str, input : &Input, index : usize) -> Option<(usize, T)> {
+        let grammar = self.grammar;
+        let rule = grammar.index(name).expect("rule names are checked before parsing");
+        let key = (rule, input.id, input.items.len(), index);
+        if grammar.memoize {
+            if let Some(ret) = self.memo.get(&key) {
+                return ret.clone();
+            }
+        }
+        if !self.active.insert(key) {
+            return None;
+        }
+
+        let mut ret = None;
+        for alternative in &grammar.rules[rule].1 {
+            let mut collected = Collected { values: vec![], branches: vec![], captures: vec![] };
+            if let Some(end) = self.peg(&alternative.peg, input, index, index, &mut collected) {
+                let Collected { values, branches, captures } = collected;
+                let m = Match { items: input.items[index..end].to_vec(), branches, captures };
+                ret = Some((end, (alternative.f.borrow_mut())(m, values)));
+                break;
+            }
+        }
+
+        self.active.remove(&key);
+        if grammar.memoize {
+            self.memo.insert(key, ret.clone());
+        }
+        ret
+    }
+
+    /// Returns the position after the expression.  Captures are relative to the start of the rule.
+    fn peg(&mut self, peg : &Peg, input : &Input, start : usize, index : usize, collected : &mut Collected<T>) -> Option<usize> {
+        match peg {
+            Peg::Item(p) => match prefix_match(p, &input.items[index..]) {
+                Ok(found) => {
+                    let offset = index - start;
+                    collected.branches.extend(found.branches);
+                    collected.captures.extend(found.captures.into_iter().map(|(n, r)| (n, r.start + offset..r.end + offset)));
+                    Some(index + found.end)
+                },
+                Err((furthest, expected)) => {
+                    self.expect(input, index + furthest, expected);
+                    None
+                },
+            },
+            Peg::Rule(name) => {
+                let (end, value) = self.rule(name, input, index)?;
+                collected.values.push(value);
+                Some(end)
+            },
+            Peg::Parse(name, p) => {
+                let item = match input.items.get(index) {
+                    Some(item) if p.matches_item(item) => item,
+                    _ => {
+                        self.expect(input, index, vec![p.describe()]);
+                        return None;
+                    },
+                };
+                let single = std::slice::from_ref(item);
+                let next_id = self.nested.len() + 1;
+                let id = *self.nested.entry((input.id, index)).or_insert(next_id);
+                let contents = Input { id, items: item.contents().unwrap_or(single), end: LMeta::single(item.meta().end) };
+                let value = self.all(name, &contents, "closing bracket")?;
+                collected.values.push(value);
+                Some(index + 1)
+            },
+            Peg::Seq(ps) => {
+                let mark = collected.mark();
+                let mut next = index;
+                for p in ps {
+                    match self.peg(p, input, start, next, collected) {
+                        Some(end) => { next = end; },
+                        None => {
+                            collected.reset(mark);
+                            return None;
+                        },
+                    }
+                }
+                Some(next)
+            },
+            Peg::Choice(ps) => {
+                for (branch, p) in ps.iter().enumerate() {
+                    let mark = collected.mark();
+                    collected.branches.push(branch);
+                    match self.peg(p, input, start, index, collected) {
+                        Some(end) => { return Some(end); },
+                        None => { collected.reset(mark); },
+                    }
+                }
+                None
+            },
+            Peg::Optional(p) => {
+                let mark = collected.mark();
+                match self.peg(p, input, start, index, collected) {
+                    Some(end) => Some(end),
+                    None => {
+                        collected.reset(mark);
+                        Some(index)
+                    },
+                }
+            },
+            Peg::ZeroOrMore(p) => Some(self.repeat(p, input, start, index, collected)),
+            Peg::OneOrMore(p) => {
+                let next = self.peg(p, input, start, index, collected)?;
+                Some(self.repeat(p, input, start, next, collected))
+            },
+            Peg::Not(p) => {
+                let ret = self.lookahead(p, input, start, index, collected);
+                match ret {
+                    Some(_) => None,
+                    None => Some(index),
+                }
+            },
+            Peg::And(p) => {
+                let ret = self.lookahead(p, input, start, index, collected);
+                ret.map(|_| index)
+            },
+        }
+    }
+
+    /// Matches without keeping any results and without reporting what the expression expected.
+    fn lookahead(&mut self, peg : &Peg, input : &Input, start : usize, index : usize, collected : &mut Collected<T>) -> Option<usize> {
+        let mark = collected.mark();
+        let furthest = self.furthest.clone();
+        let ret = self.peg(peg, input, start, index, collected);
+        collected.reset(mark);
+        self.furthest = furthest;
+        ret
+    }
+
+    /// Stops at the first failure or when the expression stops consuming items.
+    fn repeat(&mut self, peg : &Peg, input : &Input, start : usize, mut index : usize, collected : &mut Collected<T>) -> usize {
+        loop {
+            let mark = collected.mark();
+            match self.peg(peg, input, start, index, collected) {
+                Some(end) if end > index => { index = end; },
+                _ => {
+                    collected.reset(mark);
+                    return index;
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::rc::Rc;
+    use std::cell::Cell;
+    use crate::data::Lexeme;
+    use crate::parsing::{lexer, bracketer};
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Ast {
+        Block(Vec<Ast>),
+        If(Box<Ast>, Box<Ast>),
+        IfElse(Box<Ast>, Box<Ast>, Box<Ast>),
+        Call(String, Vec<Ast>),
+        Var(String),
+    }
+
+    fn brackets(input : &str) -> Vec<Bracket> {
+        bracketer::bracket(lexer::lex(input).unwrap().into_iter()).unwrap()
+    }
+    fn sym(s : &str) -> Peg { Peg::Item(Pattern::Exact(Bracket::Lex(Lexeme::Symbol(LMeta::new(), s.to_string())))) }
+    fn punct(c : char) -> Peg { Peg::Item(Pattern::Exact(Bracket::Lex(Lexeme::Punct(LMeta::new(), c)))) }
+    fn rule(s : &str) -> Peg { Peg::Rule(s.to_string()) }
+    fn is_paren(x : &Bracket) -> bool { matches!(x, Bracket::Paren(_, _)) }
+    fn is_curl(x : &Bracket) -> bool { matches!(x, Bracket::Curl(_, _)) }
+    fn is_sym(x : &Bracket) -> bool { matches!(x, Bracket::Lex(Lexeme::Symbol(_, _))) }
+    fn name(m : &Match<Bracket>) -> String { m.items[0].value() }
+
+    fn grammar() -> PegGrammar<Ast> {
+        let paren = |name : &str| Peg::Parse(name.into(), Pattern::Expect("`(`".into(), Box::new(Pattern::Pred(is_paren))));
+        let curl = |name : &str| Peg::Parse(name.into(), Pattern::Expect("`{`".into(), Box::new(Pattern::Pred(is_curl))));
+        let var = || Peg::Item(Pattern::Expect("a variable".into(), Box::new(Pattern::Pred(is_sym))));
+
+        PegGrammar::new()
+            .rule("block", vec![PegRule::new(Peg::ZeroOrMore(Box::new(rule("stmt"))), |_, xs| Ast::Block(xs))])
+            .rule("stmt", vec![ PegRule::new( Peg::Seq(vec![sym("if"), paren("expr"), curl("block"), sym("else"), curl("block")])
+                                            , |_, mut xs| {
+                                                let (c, b) = (xs.pop().unwrap(), xs.pop().unwrap());
+                                                Ast::IfElse(Box::new(xs.pop().unwrap()), Box::new(b), Box::new(c))
+                                            })
+                              , PegRule::new( Peg::Seq(vec![sym("if"), paren("expr"), curl("block")])
+                                            , |_, mut xs| {
+                                                let b = xs.pop().unwrap();
+                                                Ast::If(Box::new(xs.pop().unwrap()), Box::new(b))
+                                            })
+                              , PegRule::new(Peg::Seq(vec![rule("expr"), punct(';')]), |_, mut xs| xs.pop().unwrap())
+                              ])
+            .rule("expr", vec![ PegRule::new(Peg::Seq(vec![var(), paren("args")]), |m, xs| Ast::Call(name(&m), xs))
+                              , PegRule::new(Peg::Seq(vec![Peg::Not(Box::new(sym("if"))), var()]), |m, _| Ast::Var(name(&m)))
+                              ])
+            .rule("args", vec![PegRule::new( Peg::Optional(Box::new(Peg::Seq(vec![ rule("expr")
+                                                                                  , Peg::ZeroOrMore(Box::new(Peg::Seq(vec![punct(','), rule("expr")])))
+                                                                                  ])))
+                                           , |_, xs| Ast::Block(xs))])
+    }
+
+    #[test]
+    fn should_parse_shared_prefixes_with_backtracking() {
+        let input = brackets("if (f(x, y)) { a; } else { if (b) { g(); } } c;");
+        let output = grammar().parse("block", &input).unwrap();
+        let var = |s : &str| Ast::Var(s.into());
+        let expected = Ast::Block(vec![ Ast::IfElse( Box::new(Ast::Call("f".into(), vec![Ast::Block(vec![var("x"), var("y")])]))
+                                                   , Box::new(Ast::Block(vec![var("a")]))
+                                                   , Box::new(Ast::Block(vec![Ast::If( Box::new(var("b"))
+                                                                                     , Box::new(Ast::Block(vec![Ast::Call("g".into(), vec![Ast::Block(vec![])])]))
+                                                                                     )]))
+                                                   )
+                                      , var("c")
+                                      ]);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn should_report_furthest_failure() {
+        let input = brackets("a; if (b) { c }");
+        assert_eq!(grammar().parse("block", &input), Err(PegError::Expected(LMeta::single(14), vec!["`(`".into(), "`;`".into()])));
+
+        let input = brackets("a; if b");
+        assert_eq!(grammar().parse("block", &input), Err(PegError::Expected(LMeta::single(6), vec!["`(`".into()])));
+
+        let input = brackets("f(a b)");
+        assert_eq!(grammar().parse("block", &input), Err(PegError::Expected(LMeta::single(4), vec!["`(`".into(), "`,`".into(), "closing bracket".into()])));
+
+        let grammar = PegGrammar::<Ast>::new().rule("top", vec![PegRule::new(rule("missing"), |_, _| Ast::Block(vec![]))]);
+        assert_eq!(grammar.parse("top", &[]), Err(PegError::UnknownRule("missing".into())));
+    }
+
+    #[test]
+    fn should_memoize_and_stop_left_recursion() {
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let grammar = |memoize| PegGrammar::new()
+            .memoize(memoize)
+            .rule("top", vec![ PegRule::new(Peg::Seq(vec![rule("item"), punct(';')]), |_, xs| xs.len())
+                             , PegRule::new(Peg::Seq(vec![rule("item"), punct(',')]), |_, xs| xs.len())
+                             ])
+            .rule("item", vec![PegRule::new(Peg::Item(Pattern::Pred(is_sym)), { let counter = counter.clone(); move |_, _| { counter.set(counter.get() + 1); 0 } })]);
+
+        let input = brackets("a,");
+        assert_eq!(grammar(false).parse("top", &input), Ok(1));
+        assert_eq!(calls.get(), 2);
+        assert_eq!(grammar(true).parse("top", &input), Ok(1));
+        assert_eq!(calls.get(), 3);
+
+        let grammar = PegGrammar::new()
+            .rule("expr", vec![ PegRule::new(Peg::Seq(vec![rule("expr"), punct('+'), rule("expr")]), |_, _| 2)
+                              , PegRule::new(Peg::Item(Pattern::Wild), |_, _| 1)
+                              ]);
+        assert_eq!(grammar.parse("expr", &brackets("x")), Ok(1));
+    }
+
+    #[test]
+    fn should_memoize_nested_inputs_separately() {
+        let grammar = |memoize| PegGrammar::new()
+            .memoize(memoize)
+            .rule("top", vec![ PegRule::new(Peg::Seq(vec![Peg::Parse("inner".into(), Pattern::Pred(is_sym)), punct('!')]), |_, xs| xs[0])
+                             , PegRule::new(Peg::Seq(vec![rule("inner"), punct(';')]), |_, xs| xs[0])
+                             ])
+            .rule("inner", vec![ PegRule::new(Peg::Seq(vec![Peg::Item(Pattern::Wild), Peg::Item(Pattern::Wild)]), |m, _| m.items.len())
+                               , PegRule::new(Peg::Item(Pattern::Wild), |m, _| m.items.len())
+                               ]);
+
+        let input = brackets("a b;");
+        assert_eq!(grammar(false).parse("top", &input), Ok(2));
+        assert_eq!(grammar(true).parse("top", &input), Ok(2));
+    }
+}
@@ -0,0 +1,162 @@
+
+use crate::data::{Bracket, LMeta};
+use super::bracket::{Pattern, buffer_meta};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trailing {
+    /// A separator after the last segment is an error.
+    Forbid,
+    /// A separator after the last segment is ignored.
+    Allow,
+    /// Every segment including the last must be followed by a separator.
+    Require,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment<'a> {
+    pub items : &'a [Bracket],
+    /// Covers the items of the segment or the following separator when the segment is empty.
+    pub meta : LMeta,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SplitError {
+    EmptySegment(LMeta),
+    TrailingSeparator(LMeta),
+    MissingTrailingSeparator(LMeta),
+}
+
+impl SplitError {
+    pub fn meta(&self) -> LMeta {
+        match self {
+            SplitError::EmptySegment(m) => m.clone(),
+            SplitError::TrailingSeparator(m) => m.clone(),
+            SplitError::MissingTrailingSeparator(m) => m.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for SplitError {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SplitError::EmptySegment(m) => write!(f, "Encountered empty segment before separator: {}", m.start),
+            SplitError::TrailingSeparator(m) => write!(f, "Encountered separator after last segment: {}", m.start),
+            SplitError::MissingTrailingSeparator(m) => write!(f, "Expected separator after last segment: {}", m.end),
+        }
+    }
+}
+
+impl std::error::Error for SplitError { }
+
+/// Splits bracket contents into the segments between items that match a separator pattern.
+#[derive(Debug)]
+pub struct Splitter {
+    separator : Pattern,
+    trailing : Trailing,
+    allow_empty : bool,
+}
+
+impl Splitter {
+    pub fn new(separator : Pattern) -> Self {
+        Splitter { separator, trailing: Trailing::Allow, allow_empty: false }
+    }
+    pub fn trailing(mut self, trailing : Trailing) -> Self {
+        self.trailing = trailing;
+        self
+    }
+    pub fn allow_empty(mut self, enable : bool) -> Self {
+        self.allow_empty = enable;
+        self
+    }
+
+    /// Empty input yields no segments.
+    pub fn split<'a>(&self, input : &'a [Bracket]) -> Result<Vec<Segment<'a>>, SplitError> {
+        let mut ret = vec![];
+        let mut start = 0;
+        let mut last_separator = None;
+        for (index, item) in input.iter().enumerate() {
+            if !self.separator.matches_item(item) {
+                continue;
+            }
+            let items = &input[start..index];
+            if items.is_empty() && !self.allow_empty {
+                return Err(SplitError::EmptySegment(item.meta()));
+            }
+            let meta = if items.is_empty() { item.meta() } else { buffer_meta(items) };
+            ret.push(Segment { items, meta });
+            start = index + 1;
+            last_separator = Some(item);
+        }
+
+        let rest = &input[start..];
+        match (rest.is_empty(), last_separator, self.trailing) {
+            (true, None, _) => { },
+            (true, Some(sep), Trailing::Forbid) => { return Err(SplitError::TrailingSeparator(sep.meta())); },
+            (true, Some(_), _) => { },
+            (false, _, Trailing::Require) => { return Err(SplitError::MissingTrailingSeparator(buffer_meta(rest))); },
+            (false, _, _) => { ret.push(Segment { items: rest, meta: buffer_meta(rest) }); },
+        }
+        Ok(ret)
+    }
+}
+
+/// Splits with the default `Splitter` which allows a trailing separator and forbids empty segments.
+pub fn split(input : &[Bracket], separator : Pattern) -> Result<Vec<Segment<'_>>, SplitError> {
+    Splitter::new(separator).split(input)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::Lexeme;
+    use crate::parsing::{lexer, bracketer};
+
+    fn brackets(input : &str) -> Vec<Bracket> {
+        bracketer::bracket(lexer::lex(input).unwrap().into_iter()).unwrap()
+    }
+
+    fn sep(c : char) -> Pattern {
+        Pattern::Exact(Bracket::Lex(Lexeme::Punct(LMeta::new(), c)))
+    }
+
+    fn values(segments : &[Segment]) -> Vec<String> {
+        segments.iter().map(|s| s.items.iter().map(|x| x.value()).collect::<Vec<_>>().join(" ")).collect()
+    }
+
+    #[test]
+    fn should_split_bracket_contents() {
+        let input = brackets("y(1, 2 + 3, (4, 5))");
+        let segments = split(input[1].contents().unwrap(), sep(',')).unwrap();
+        assert_eq!(values(&segments), vec!["1", "2 + 3", "(4,5)"]);
+        assert_eq!(segments[1].meta, LMeta::multi(5, 9));
+
+        let input = brackets("a = 1; b = 2;");
+        let segments = split(&input, sep(';')).unwrap();
+        assert_eq!(values(&segments), vec!["a = 1", "b = 2"]);
+
+        let either = Pattern::Alt(vec![sep(','), sep(';')]);
+        let input = brackets("a, b; c");
+        let segments = split(&input, either).unwrap();
+        assert_eq!(values(&segments), vec!["a", "b", "c"]);
+
+        assert_eq!(split(&[], sep(',')).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn should_apply_trailing_and_empty_options() {
+        let input = brackets("a, b,");
+        let forbid = Splitter::new(sep(',')).trailing(Trailing::Forbid);
+        assert_eq!(forbid.split(&input), Err(SplitError::TrailingSeparator(LMeta::single(4))));
+        assert_eq!(forbid.split(&input[..3]).map(|s| values(&s)), Ok(vec!["a".into(), "b".into()]));
+
+        let require = Splitter::new(sep(';')).trailing(Trailing::Require);
+        assert_eq!(require.split(&brackets("a; b;")).map(|s| s.len()), Ok(2));
+        assert_eq!(require.split(&brackets("a; b c")), Err(SplitError::MissingTrailingSeparator(LMeta::multi(3, 5))));
+
+        let input = brackets("a,, b");
+        assert_eq!(split(&input, sep(',')), Err(SplitError::EmptySegment(LMeta::single(2))));
+        let segments = Splitter::new(sep(',')).allow_empty(true).split(&input).unwrap();
+        assert_eq!(values(&segments), vec!["a", "", "b"]);
+        assert_eq!(segments[1].meta, LMeta::single(2));
+    }
+}
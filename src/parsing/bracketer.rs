@@ -8,6 +8,16 @@ pub enum BracketError {
     NotAllInputConsumed(usize),
 }
 
+impl BracketError {
+    pub fn meta(&self) -> LMeta {
+        match self {
+            BracketError::MissingEndBracket { terminal, .. } => LMeta::single(*terminal),
+            BracketError::EofInsteadOfEndBracket { initial, .. } => LMeta::single(*initial),
+            BracketError::NotAllInputConsumed(index) => LMeta::single(*index),
+        }
+    }
+}
+
 impl std::fmt::Display for BracketError {
     fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -24,6 +34,7 @@ impl std::fmt::Display for BracketError {
 
 impl std::error::Error for BracketError { }
 
+#[derive(Clone, Copy, PartialEq)]
 enum Type {
     Paren,
     Angle,
@@ -71,16 +82,17 @@ fn parse_ast(input : &mut impl Iterator<Item = Lexeme>) -> Result<(Option<Lexeme
     Ok((end, ret))
 }
 
-fn parse_bracket(t : Type, initial : usize, input : &mut impl Iterator<Item = Lexeme>) -> Result<Bracket, BracketError> {
-    fn to_expected(t : Type) -> char {
-        match t {
-            Type::Paren => ')',
-            Type::Angle => '>',
-            Type::Curl => '}',
-            Type::Square => ']',
-        }
+fn to_expected(t : Type) -> char {
+    match t {
+        Type::Paren => ')',
+        Type::Angle => '>',
+        Type::Curl => '}',
+        Type::Square => ']',
     }
+}
 
+#[allow(clippy::iter_nth_zero)]
+fn parse_bracket(t : Type, initial : usize, input : &mut impl Iterator<Item = Lexeme>) -> Result<Bracket, BracketError> {
     let (end, contents) = parse_ast(input)?;
     match (t, end) {
         (Type::Paren, Some(Lexeme::RParen(m))) => Ok(Bracket::Paren(LMeta::multi(initial, m.end), contents)),
@@ -96,6 +108,99 @@ fn parse_bracket(t : Type, initial : usize, input : &mut impl Iterator<Item = Le
     }
 }
 
+/// Brackets the whole input even when it is malformed.  Missing closing brackets are synthesized at the 
+/// end of the bracket's contents and closing brackets without a matching opening bracket become 
+/// `Bracket::Error`.  Every problem found along the way is returned with the best effort brackets.
+pub fn bracket_recover(input : impl Iterator<Item = Lexeme>) -> (Vec<Bracket>, Vec<BracketError>) {
+    let mut input = input.peekable();
+    let mut open = vec![];
+    let mut errors = vec![];
+    let ret = recover_ast(&mut input, &mut open, &mut errors);
+    (ret, errors)
+}
+
+fn close_type(l : &Lexeme) -> Option<Type> {
+    match l {
+        Lexeme::RParen(_) => Some(Type::Paren),
+        Lexeme::RAngle(_) => Some(Type::Angle),
+        Lexeme::RCurl(_) => Some(Type::Curl),
+        Lexeme::RSquare(_) => Some(Type::Square),
+        _ => None,
+    }
+}
+
+fn recover_ast<I : Iterator<Item = Lexeme>>( input : &mut std::iter::Peekable<I>
+                                           , open : &mut Vec<(Type, usize)>
+                                           , errors : &mut Vec<BracketError>
+                                           ) -> Vec<Bracket> {
+    let mut ret = vec![];
+    loop {
+        let t = match input.peek() {
+            Some(Lexeme::LParen(_)) => Type::Paren,
+            Some(Lexeme::LAngle(_)) => Type::Angle,
+            Some(Lexeme::LCurl(_)) => Type::Curl,
+            Some(Lexeme::LSquare(_)) => Type::Square,
+            Some(l) => match close_type(l) {
+                Some(t) if open.iter().any(|(o, _)| *o == t) => { break; },
+                Some(_) => {
+                    let l = input.next().unwrap();
+                    let terminal = l.meta().start;
+                    match open.last() {
+                        Some((t, initial)) => {
+                            let found = l.value().chars().next().unwrap();
+                            errors.push(BracketError::MissingEndBracket { initial: *initial, terminal, found, expected: to_expected(*t) });
+                        },
+                        None => { errors.push(BracketError::NotAllInputConsumed(terminal)); },
+                    }
+                    ret.push(Bracket::Error(l));
+                    continue;
+                },
+                None => { 
+                    ret.push(Bracket::Lex(input.next().unwrap()));
+                    continue;
+                },
+            },
+            None => { break; },
+        };
+        let initial = input.next().unwrap().meta().start;
+        ret.push(recover_bracket(t, initial, input, open, errors));
+    }
+    ret
+}
+
+fn recover_bracket<I : Iterator<Item = Lexeme>>( t : Type
+                                               , initial : usize
+                                               , input : &mut std::iter::Peekable<I>
+                                               , open : &mut Vec<(Type, usize)>
+                                               , errors : &mut Vec<BracketError>
+                                               ) -> Bracket {
+    open.push((t, initial));
+    let contents = recover_ast(input, open, errors);
+    open.pop();
+
+    let end = match input.peek() {
+        Some(l) if close_type(l) == Some(t) => input.next().unwrap().meta().end,
+        Some(l) => {
+            let found = l.value().chars().next().unwrap();
+            let terminal = l.meta().start;
+            errors.push(BracketError::MissingEndBracket { initial, terminal, found, expected: to_expected(t) });
+            contents.last().map(|b| b.meta().end).unwrap_or(initial)
+        },
+        None => {
+            errors.push(BracketError::EofInsteadOfEndBracket { initial, expected: to_expected(t) });
+            contents.last().map(|b| b.meta().end).unwrap_or(initial)
+        },
+    };
+
+    let meta = LMeta::multi(initial, end);
+    match t {
+        Type::Paren => Bracket::Paren(meta, contents),
+        Type::Angle => Bracket::Angle(meta, contents),
+        Type::Curl => Bracket::Curl(meta, contents),
+        Type::Square => Bracket::Square(meta, contents),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -152,4 +257,72 @@ mod test {
         assert!(matches!(items[0], Bracket::Lex(Lexeme::Number(_, _))));
         assert!(matches!(items[1], Bracket::Paren(_, _)));
     }
+
+    #[test]
+    fn should_locate_bracket_error() {
+        let input = "( 1 2\n ]";
+        let tokens = lex(input).unwrap().into_iter();
+        let error = bracket(tokens).unwrap_err();
+        assert!(matches!(error, BracketError::MissingEndBracket { found: ']', expected: ')', .. }));
+        let map = crate::data::SourceMap::new(input);
+        assert_eq!(map.span(&error.meta()).to_string(), "2:2-2:2");
+    }
+
+    #[test]
+    fn should_recover_from_missing_end_bracket() {
+        let input = "{ ( 1 } 2 [ 3";
+        let tokens = lex(input).unwrap().into_iter();
+        let (ast, errors) = bracket_recover(tokens);
+        assert_eq!(ast.len(), 3);
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0], BracketError::MissingEndBracket { initial: 2, terminal: 6, found: '}', expected: ')' }));
+        assert!(matches!(errors[1], BracketError::EofInsteadOfEndBracket { initial: 10, expected: ']' }));
+
+        let items = match &ast[0] {
+            Bracket::Curl(m, items) => { assert_eq!(*m, LMeta::multi(0, 6)); items },
+            _ => panic!("expected curl"),
+        };
+        assert_eq!(items.len(), 1);
+        assert!(matches!(&items[0], Bracket::Paren(m, xs) if *m == LMeta::multi(2, 4) && xs.len() == 1));
+        assert!(matches!(ast[1], Bracket::Lex(Lexeme::Number(_, _))));
+        assert!(matches!(&ast[2], Bracket::Square(m, xs) if *m == LMeta::multi(10, 12) && xs.len() == 1));
+    }
+
+    #[test]
+    fn should_recover_from_unmatched_end_bracket() {
+        let input = "] ( 1 > 2 )";
+        let tokens = lex(input).unwrap().into_iter();
+        let (ast, errors) = bracket_recover(tokens);
+        assert_eq!(ast.len(), 2);
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0], BracketError::NotAllInputConsumed(0)));
+        assert!(matches!(errors[1], BracketError::MissingEndBracket { initial: 2, terminal: 6, found: '>', expected: ')' }));
+        assert!(matches!(ast[0], Bracket::Error(Lexeme::RSquare(_))));
+
+        let items = match &ast[1] {
+            Bracket::Paren(_, items) => items,
+            _ => panic!("expected paren"),
+        };
+        assert_eq!(items.len(), 3);
+        assert!(matches!(items[1], Bracket::Error(Lexeme::RAngle(_))));
+    }
+
+    #[test]
+    fn should_recover_without_errors() {
+        let input = "a ( 1 [ 2 ] ) { }";
+        let expected = bracket(lex(input).unwrap().into_iter()).unwrap();
+        let (ast, errors) = bracket_recover(lex(input).unwrap().into_iter());
+        assert_eq!(errors.len(), 0);
+        assert_eq!(ast, expected);
+    }
+
+    #[test]
+    fn should_reproduce_source_from_brackets_with_trivia() {
+        let input = "f( a, [ 1 /* ) */ ] ) { \"}\" }\n";
+        let tokens = crate::parsing::lexer::LexerConfig::new().trivia(true).lex(input).unwrap();
+        let ast = bracket(tokens.into_iter()).unwrap();
+        assert_eq!(ast.len(), 5);
+        assert_eq!(ast.iter().map(|b| b.source(input)).collect::<String>(), input);
+        assert_eq!(ast.iter().map(|b| b.text()).collect::<String>(), input);
+    }
 }
\ No newline at end of file
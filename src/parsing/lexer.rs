@@ -1,17 +1,25 @@
 
-
-use crate::data::{Lexeme, LMeta};
+use crate::data::{Lexeme, LMeta, NumberLiteral};
 
 #[derive(Debug)]
 pub enum LexError {
-    EncounteredEndInString,
+    EncounteredEndInString(usize),
     UnexpectedEscapeInString(usize, char),
 }
 
+impl LexError {
+    pub fn meta(&self) -> LMeta {
+        match self {
+            LexError::EncounteredEndInString(index) => LMeta::single(*index),
+            LexError::UnexpectedEscapeInString(index, _) => LMeta::single(*index),
+        }
+    }
+}
+
 impl std::fmt::Display for LexError {
     fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            LexError::EncounteredEndInString => write!(f, "Encountered end of file while lexing string."),
+            LexError::EncounteredEndInString(index) => write!(f, "Encountered end of file while lexing string: {}", index),
             LexError::UnexpectedEscapeInString(index, c) => write!(f, "Encountered unexpected escape in string: {}::{}", index, c),
         }
     }
@@ -19,16 +27,6 @@ impl std::fmt::Display for LexError {
 
 impl std::error::Error for LexError { }
 
-macro_rules! i {
-    ($p:pat, end) => { Some( (Some($p), None) ) };
-    ($p1:pat, $p2:pat) => { Some( (Some($p1), Some($p2)) ) };
-    ($p:pat) => { Some( (Some($p), _) ) };
-}
-
-macro_rules! input {
-    () => { &mut impl Iterator<Item = (Option<(usize, char)>, Option<(usize, char)>)> };
-}
-
 fn symbol_start(c : char) -> bool { c.is_alphabetic() || c == '_' }
 fn symbol_char(x : char) -> bool {
     x.is_alphanumeric() || x == '_' 
@@ -37,145 +35,321 @@ fn num_char(x : char) -> bool {
     x.is_numeric() 
 }
 
-pub fn lex(input : &str) -> Result<Vec<Lexeme>, LexError> {
+#[derive(Debug, Clone)]
+pub struct LexerConfig {
+    symbol_start : fn(char) -> bool,
+    symbol_char : fn(char) -> bool,
+    num_char : fn(char) -> bool,
+    line_comment : Option<String>,
+    block_comment : Option<(String, String)>,
+    string_delimiters : Vec<char>,
+    escapes : Vec<(char, char)>,
+    operators : Vec<String>,
+    number_literals : bool,
+    trivia : bool,
+}
 
-    let input = input.char_indices().map(|c| Some(c));
-    let mut input = input.clone().zip(input.skip(1).chain(std::iter::once(None)));
+impl Default for LexerConfig {
+    fn default() -> Self {
+        LexerConfig { 
+            symbol_start,
+            symbol_char,
+            num_char,
+            line_comment: Some("//".into()),
+            block_comment: Some(("/*".into(), "*/".into())),
+            string_delimiters: vec!['"'],
+            escapes: vec![('t', '\t'), ('n', '\n'), ('r', '\r'), ('0', '\0'), ('\\', '\\'), ('"', '"')],
+            operators: vec![],
+            number_literals: false,
+            trivia: false,
+        }
+    }
+}
 
-    let mut comment = 0;
+impl LexerConfig {
+    pub fn new() -> Self {
+        LexerConfig::default()
+    }
+    pub fn symbol_start(mut self, f : fn(char) -> bool) -> Self {
+        self.symbol_start = f;
+        self
+    }
+    pub fn symbol_char(mut self, f : fn(char) -> bool) -> Self {
+        self.symbol_char = f;
+        self
+    }
+    pub fn num_char(mut self, f : fn(char) -> bool) -> Self {
+        self.num_char = f;
+        self
+    }
+    pub fn line_comment(mut self, marker : Option<&str>) -> Self {
+        self.line_comment = marker.map(|m| m.to_string());
+        self
+    }
+    /// Block comments nest, so every start marker inside of a comment needs its own end marker.
+    pub fn block_comment(mut self, markers : Option<(&str, &str)>) -> Self {
+        self.block_comment = markers.map(|(s, e)| (s.to_string(), e.to_string()));
+        self
+    }
+    pub fn string_delimiters(mut self, delimiters : Vec<char>) -> Self {
+        self.string_delimiters = delimiters;
+        self
+    }
+    /// Each entry maps the character following a '\' in a string to the character it stands for.
+    pub fn escapes(mut self, escapes : Vec<(char, char)>) -> Self {
+        self.escapes = escapes;
+        self
+    }
 
-    let mut ret = vec![];
-    loop {
-        let x = input.next();
+    /// Operators are matched longest first, but only at characters which cannot start a number or a symbol.
+    pub fn operators(mut self, operators : Vec<&str>) -> Self {
+        self.operators = operators.into_iter().map(|o| o.to_string()).collect();
+        self
+    }
+
+    /// Lexes decimals, exponents, radix prefixes, digit separators and type suffixes as part of a 
+    /// single number.  See `Lexeme::number_literal` for the parsed form.
+    pub fn number_literals(mut self, enable : bool) -> Self {
+        self.number_literals = enable;
+        self
+    }
+
+    /// Keeps whitespace and comments as `Lexeme::Trivia`, so that concatenating the `text` of every
+    /// lexeme reproduces the input.
+    pub fn trivia(mut self, enable : bool) -> Self {
+        self.trivia = enable;
+        self
+    }
+
+    pub fn lex(&self, input : &str) -> Result<Vec<Lexeme>, LexError> {
+        let (ret, mut errors) = self.lex_recover(input);
+        if errors.is_empty() {
+            Ok(ret)
+        }
+        else {
+            Err(errors.remove(0))
+        }
+    }
+
+    /// Lexes the whole input even when it contains errors.  Each malformed item becomes a
+    /// `Lexeme::Error` holding its source text and every error found is returned with the lexemes.
+    pub fn lex_recover(&self, input : &str) -> (Vec<Lexeme>, Vec<LexError>) {
+        let mut input = Input::new(input);
+
+        let mut errors = vec![];
+        let mut ret = vec![];
+        while let Some((index, c)) = input.peek() {
+            if let Some((start, end)) = &self.block_comment {
+                if input.starts_with(start) {
+                    skip_block_comment(start, end, &mut input);
+                    self.push_trivia(index, &input, &mut ret);
+                    continue;
+                }
+            }
+
+            if let Some(marker) = &self.line_comment {
+                if input.starts_with(marker) {
+                    skip_line(&mut input);
+                    self.push_trivia(index, &input, &mut ret);
+                    continue;
+                }
+            }
+
+            if !(self.num_char)(c) && !(self.symbol_start)(c) {
+                if let Some(o) = self.operators.iter().filter(|o| input.starts_with(o)).max_by_key(|o| o.len()) {
+                    input.skip(o);
+                    ret.push(Lexeme::Operator(LMeta::multi(index, input.previous()), o.clone()));
+                    continue;
+                }
+            }
 
-        if comment > 0 {
-            match x {
-                i!((_, '/'), (_, '*')) => { comment += 1; input.next(); },
-                i!((_, '*'), (_, '/')) => { comment -= 1; input.next(); },
-                _ => { },
+            match c {
+                c if c.is_whitespace() => { 
+                    while matches!(input.peek(), Some((_, c)) if c.is_whitespace()) {
+                        input.next();
+                    }
+                    self.push_trivia(index, &input, &mut ret);
+                },
+
+                ')' => { input.next(); ret.push(Lexeme::RParen(LMeta::single(index))); },
+                '(' => { input.next(); ret.push(Lexeme::LParen(LMeta::single(index))); },
+
+                '>' => { input.next(); ret.push(Lexeme::RAngle(LMeta::single(index))); },
+                '<' => { input.next(); ret.push(Lexeme::LAngle(LMeta::single(index))); },
+
+                '}' => { input.next(); ret.push(Lexeme::RCurl(LMeta::single(index))); },
+                '{' => { input.next(); ret.push(Lexeme::LCurl(LMeta::single(index))); },
+
+                ']' => { input.next(); ret.push(Lexeme::RSquare(LMeta::single(index))); },
+                '[' => { input.next(); ret.push(Lexeme::LSquare(LMeta::single(index))); },
+
+                c if self.number_literals && c.is_ascii_digit() => {
+                    let (len, _) = NumberLiteral::scan(input.rest()).unwrap();
+                    let item = input.rest()[..len].to_string();
+                    input.skip(&item);
+                    ret.push(Lexeme::Number(LMeta::multi(index, input.previous()), item));
+                },
+
+                c if (self.num_char)(c) => {
+                    let (meta, item) = lex_item(self.num_char, &mut input);
+                    ret.push(Lexeme::Number(meta, item));
+                },
+
+                c if (self.symbol_start)(c) => {
+                    let (meta, item) = lex_item(self.symbol_char, &mut input);
+                    ret.push(Lexeme::Symbol(meta, item));
+                },
+
+                c if self.string_delimiters.contains(&c) => {
+                    let s = self.lex_string(&mut input, &mut errors);
+                    ret.push(s);
+                },
+
+                c => { input.next(); ret.push(Lexeme::Punct(LMeta::single(index), c)); },
             }
-            continue;
         }
 
-        match x {
-            i!((_, c)) if c.is_whitespace() => { },
-            i!((_, '/'), (_, '*')) => { comment += 1; input.next(); },
-            i!((_, '/'), (_, '/')) => { skip_line(&mut input); },
-
-            i!((index, ')')) => { ret.push(Lexeme::RParen(LMeta::single(index))); },
-            i!((index, '(')) => { ret.push(Lexeme::LParen(LMeta::single(index))); },
-            
-            i!((index, '>')) => { ret.push(Lexeme::RAngle(LMeta::single(index))); },
-            i!((index, '<')) => { ret.push(Lexeme::LAngle(LMeta::single(index))); },
-
-            i!((index, '}')) => { ret.push(Lexeme::RCurl(LMeta::single(index))); },
-            i!((index, '{')) => { ret.push(Lexeme::LCurl(LMeta::single(index))); },
-
-            i!((index, ']')) => { ret.push(Lexeme::RSquare(LMeta::single(index))); },
-            i!((index, '[')) => { ret.push(Lexeme::LSquare(LMeta::single(index))); },
-
-            i!((index, c), (_, other)) if num_char(c) && num_char(other) => {
-                let num = lex_number(c, index, &mut input)?;
-                ret.push(num);
-            },
-            i!((index, c)) if num_char(c) => {
-                let num = Lexeme::Number(LMeta::single(index), c.to_string());
-                ret.push(num);
-            },
-
-            i!((index, c), (_, other)) if symbol_start(c) && symbol_char(other) => {
-                let sym = lex_symbol(c, index, &mut input)?;
-                ret.push(sym);
-            },
-            i!((index, c)) if symbol_start(c) => {
-                let num = Lexeme::Symbol(LMeta::single(index), c.to_string());
-                ret.push(num);
-            },
-
-            i!((index, '"')) => {
-                let s = lex_string(index, &mut input)?;
-                ret.push(s);
-            },
-            i!((index, c)) => { ret.push(Lexeme::Punct(LMeta::single(index), c)); },
-            None => { break; },
-            _ => unreachable!(),
+        (ret, errors)
+    }
+
+    fn push_trivia(&self, start : usize, input : &Input, ret : &mut Vec<Lexeme>) {
+        if self.trivia {
+            ret.push(Lexeme::Trivia(LMeta::multi(start, input.previous()), input.slice(start).to_string()));
         }
     }
 
-    Ok(ret)
-}
+    fn lex_string(&self, input : &mut Input, errors : &mut Vec<LexError>) -> Lexeme {
+        let (start, delimiter) = input.next().unwrap();
+        let error_count = errors.len();
+
+        let mut ret = vec![];
+        loop {
+            match input.next() {
+                Some((_, c)) if c == delimiter => { break; },
+                Some((_, '\\')) => match input.next() {
+                    Some((index, c)) => match self.escapes.iter().find(|(e, _)| *e == c) {
+                        Some((_, value)) => { ret.push(*value); },
+                        None => { errors.push(LexError::UnexpectedEscapeInString(index, c)); },
+                    },
+                    None => { errors.push(LexError::EncounteredEndInString(start)); break; },
+                },
+                Some((_, c)) => { ret.push(c); },
+                None => { errors.push(LexError::EncounteredEndInString(start)); break; },
+            }
+        }
 
-fn skip_line(input : input!()) {
-    loop {
-        match input.next() {
-            i!(_, (_, c)) if c == '\n' || c == '\r' => { break; },
-            None => { break; },
-            _ => { },
+        let meta = LMeta::multi(start, input.previous());
+        if errors.len() == error_count {
+            Lexeme::String(meta, ret.into_iter().collect::<String>(), input.slice(start).to_string())
+        }
+        else {
+            Lexeme::Error(meta, input.slice(start).to_string())
         }
     }
 }
 
-fn lex_item(c : char, start : usize, target : fn(char) -> bool, input : input!()) -> Result<(LMeta, String), LexError> {
-    let mut end = start;
+pub fn lex(input : &str) -> Result<Vec<Lexeme>, LexError> {
+    LexerConfig::default().lex(input)
+}
 
-    let mut ret = vec![c];
+pub fn lex_recover(input : &str) -> (Vec<Lexeme>, Vec<LexError>) {
+    LexerConfig::default().lex_recover(input)
+}
+
+struct Input<'a> {
+    text : &'a str,
+    chars : Vec<(usize, char)>,
+    index : usize,
+}
+
+impl<'a> Input<'a> {
+    fn new(text : &'a str) -> Self {
+        Input { text, chars: text.char_indices().collect(), index: 0 }
+    }
+    fn peek(&self) -> Option<(usize, char)> {
+        self.chars.get(self.index).copied()
+    }
+    fn next(&mut self) -> Option<(usize, char)> {
+        let ret = self.peek();
+        if ret.is_some() {
+            self.index += 1;
+        }
+        ret
+    }
+    fn starts_with(&self, s : &str) -> bool {
+        match self.peek() {
+            Some((offset, _)) => !s.is_empty() && self.text[offset..].starts_with(s),
+            None => false,
+        }
+    }
+    fn skip(&mut self, s : &str) {
+        self.index += s.chars().count();
+    }
+    fn rest(&self) -> &'a str {
+        match self.peek() {
+            Some((offset, _)) => &self.text[offset..],
+            None => "",
+        }
+    }
+    fn previous(&self) -> usize {
+        self.chars[self.index - 1].0
+    }
+    fn slice(&self, start : usize) -> &'a str {
+        &self.text[start..self.text.len() - self.rest().len()]
+    }
+}
+
+fn skip_line(input : &mut Input) {
     loop {
-        match input.next() {
-            i!((index, c), (_, x)) if target(c) && !target(x) => {
-                ret.push(c);
-                end = index;
-                break;
-            },
-            i!((index, c), end) => if target(c) {
-                ret.push(c);
-                end = index;
-                break;
-            },
-            i!((_, c)) if target(c) => { ret.push(c); },
-            i!(_) => { break; },
+        match input.peek() {
+            Some((_, c)) if c == '\n' || c == '\r' => { break; },
             None => { break; },
-            _ => unreachable!(),
+            _ => { input.next(); },
         }
     }
-
-    Ok((LMeta::multi(start, end), ret.into_iter().collect::<String>()))
-} 
-
-fn lex_number(c : char, start : usize, input : input!()) -> Result<Lexeme, LexError> {
-    let (meta, item) = lex_item(c, start, num_char, input)?;
-    Ok(Lexeme::Number(meta, item))
 }
 
-fn lex_symbol(c : char, start : usize, input : input!()) -> Result<Lexeme, LexError> {
-    let (meta, item) = lex_item(c, start, symbol_char, input)?;
-    Ok(Lexeme::Symbol(meta, item))
+fn skip_block_comment(start : &str, end : &str, input : &mut Input) {
+    let mut comment = 0;
+    loop {
+        if input.starts_with(start) {
+            comment += 1;
+            input.skip(start);
+        }
+        else if input.starts_with(end) {
+            comment -= 1;
+            input.skip(end);
+            if comment == 0 {
+                break;
+            }
+        }
+        else if input.next().is_none() {
+            break;
+        }
+    }
 }
 
-fn lex_string(start : usize, input : input!()) -> Result<Lexeme, LexError> {
-    let end;
+fn lex_item(target : fn(char) -> bool, input : &mut Input) -> (LMeta, String) {
+    let (start, c) = input.next().unwrap();
+    let mut end = start;
 
-    let mut ret = vec![];
-    loop {
-        match input.next() {
-            i!((index, '"')) => { end = index; break; },
-            i!((_, '\\'), (_, 't')) => { ret.push('\t'); input.next(); },
-            i!((_, '\\'), (_, 'n')) => { ret.push('\n'); input.next(); },
-            i!((_, '\\'), (_, 'r')) => { ret.push('\r'); input.next(); },
-            i!((_, '\\'), (_, '0')) => { ret.push('\0'); input.next(); },
-            i!((_, '\\'), (_, '\\')) => { ret.push('\\'); input.next(); },
-            i!((_, '\\'), (_, '"')) => { ret.push('"'); input.next(); },
-            i!((_, '\\'), (index, c)) => { return Err(LexError::UnexpectedEscapeInString(index, c)); },
-            i!((_, c)) => { ret.push(c); },
-            None => { return Err(LexError::EncounteredEndInString); },
-            _ => unreachable!(),
+    let mut ret = vec![c];
+    while let Some((index, c)) = input.peek() {
+        if !target(c) {
+            break;
         }
+        ret.push(c);
+        end = index;
+        input.next();
     }
-    Ok(Lexeme::String(LMeta::multi(start, end), ret.into_iter().collect::<String>()))
-}
+
+    (LMeta::multi(start, end), ret.into_iter().collect::<String>())
+} 
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::data::NumberKind;
 
     #[test]
     fn should_handle_whitespace() {
@@ -322,7 +496,7 @@ mod test {
         let input = " \"string \\t \\n \\r \\0 \\\\ \\\" \"";
         let output = lex(input).unwrap();
         assert_eq!(output.len(), 1);
-        assert!(matches!(output[0], Lexeme::String(_, _)));
+        assert!(matches!(output[0], Lexeme::String(_, _, _)));
         assert_eq!(output[0].meta(), LMeta::multi(1, 27));
         assert_eq!(output[0].value(), "string \t \n \r \0 \\ \" ");
     }
@@ -350,4 +524,178 @@ mod test {
         assert!(matches!(output[15], Lexeme::Punct(_, '$')));
         assert!(matches!(output[16], Lexeme::Punct(_, '^')));
     }
+
+    #[test]
+    fn should_lex_with_custom_symbols() {
+        fn start(c : char) -> bool { c.is_alphabetic() || c == '$' }
+        fn rest(c : char) -> bool { c.is_alphanumeric() || c == '-' || c == '?' }
+
+        let config = LexerConfig::new().symbol_start(start).symbol_char(rest);
+        let output = config.lex("$x empty? kebab-case").unwrap();
+        assert_eq!(output.len(), 3);
+        assert_eq!(output[0], Lexeme::Symbol(LMeta::multi(0, 1), "$x".into()));
+        assert_eq!(output[1], Lexeme::Symbol(LMeta::multi(3, 8), "empty?".into()));
+        assert_eq!(output[2], Lexeme::Symbol(LMeta::multi(10, 19), "kebab-case".into()));
+    }
+
+    #[test]
+    fn should_lex_with_custom_comments() {
+        let config = LexerConfig::new().line_comment(Some("#")).block_comment(Some(("(*", "*)")));
+        let input = "1 # comment // 
+(* block (* nested *) // *) 2 // 3";
+        let output = config.lex(input).unwrap();
+        assert_eq!(output.len(), 5);
+        assert_eq!(output[0].value(), "1");
+        assert_eq!(output[1].value(), "2");
+        assert!(matches!(output[2], Lexeme::Punct(_, '/')));
+        assert!(matches!(output[3], Lexeme::Punct(_, '/')));
+        assert_eq!(output[4].value(), "3");
+    }
+
+    #[test]
+    fn should_lex_without_comments() {
+        let config = LexerConfig::new().line_comment(None).block_comment(None);
+        let output = config.lex("/*/").unwrap();
+        assert_eq!(output.len(), 3);
+        assert!(matches!(output[0], Lexeme::Punct(_, '/')));
+        assert!(matches!(output[1], Lexeme::Punct(_, '*')));
+        assert!(matches!(output[2], Lexeme::Punct(_, '/')));
+    }
+
+    #[test]
+    fn should_lex_string_with_custom_delimiters_and_escapes() {
+        let config = LexerConfig::new().string_delimiters(vec!['\'', '"']).escapes(vec![('\'', '\''), ('e', '!')]);
+        let output = config.lex(r#"'it\'s\e' "x""#).unwrap();
+        assert_eq!(output.len(), 2);
+        assert_eq!(output[0], Lexeme::String(LMeta::multi(0, 8), "it's!".into(), r"'it\'s\e'".into()));
+        assert_eq!(output[1], Lexeme::String(LMeta::multi(10, 12), "x".into(), "\"x\"".into()));
+
+        let output = config.lex(r#"'\n'"#);
+        assert!(matches!(output, Err(LexError::UnexpectedEscapeInString(2, 'n'))));
+    }
+
+    #[test]
+    fn should_lex_operators_with_longest_match() {
+        let config = LexerConfig::new().operators(vec!["=", "==", "->", "<=", "::", "&&", "-"]);
+        let output = config.lex("a::b == c->d <= e && -1 = < <- =>").unwrap();
+        assert_eq!(output.len(), 18);
+        assert_eq!(output[1], Lexeme::Operator(LMeta::multi(1, 2), "::".into()));
+        assert_eq!(output[3], Lexeme::Operator(LMeta::multi(5, 6), "==".into()));
+        assert_eq!(output[5], Lexeme::Operator(LMeta::multi(9, 10), "->".into()));
+        assert_eq!(output[7], Lexeme::Operator(LMeta::multi(13, 14), "<=".into()));
+        assert_eq!(output[9], Lexeme::Operator(LMeta::multi(18, 19), "&&".into()));
+        assert_eq!(output[10], Lexeme::Operator(LMeta::single(21), "-".into()));
+        assert!(matches!(output[11], Lexeme::Number(_, _)));
+        assert_eq!(output[12], Lexeme::Operator(LMeta::single(24), "=".into()));
+        assert!(matches!(output[13], Lexeme::LAngle(_)));
+        assert!(matches!(output[14], Lexeme::LAngle(_)));
+        assert_eq!(output[15], Lexeme::Operator(LMeta::single(29), "-".into()));
+        assert_eq!(output[16], Lexeme::Operator(LMeta::single(31), "=".into()));
+        assert!(matches!(output[17], Lexeme::RAngle(_)));
+    }
+
+    #[test]
+    fn should_lex_number_literals() {
+        let config = LexerConfig::new().number_literals(true);
+        let output = config.lex("1.5 1e-9 0xFF 0b1010 1_000_000 10u32 2.5E+3f64 1..2 3.x 0o17").unwrap();
+        assert_eq!(output.len(), 15);
+        assert_eq!(output[0], Lexeme::Number(LMeta::multi(0, 2), "1.5".into()));
+        assert_eq!(output[1], Lexeme::Number(LMeta::multi(4, 7), "1e-9".into()));
+        assert_eq!(output[2], Lexeme::Number(LMeta::multi(9, 12), "0xFF".into()));
+        assert_eq!(output[3], Lexeme::Number(LMeta::multi(14, 19), "0b1010".into()));
+        assert_eq!(output[4], Lexeme::Number(LMeta::multi(21, 29), "1_000_000".into()));
+        assert_eq!(output[5], Lexeme::Number(LMeta::multi(31, 35), "10u32".into()));
+        assert_eq!(output[6].value(), "2.5E+3f64");
+        assert_eq!(output[7].value(), "1");
+        assert!(matches!(output[8], Lexeme::Punct(_, '.')));
+        assert!(matches!(output[9], Lexeme::Punct(_, '.')));
+        assert_eq!(output[10].value(), "2");
+        assert_eq!(output[11].value(), "3");
+        assert!(matches!(output[12], Lexeme::Punct(_, '.')));
+        assert!(matches!(output[13], Lexeme::Symbol(_, _)));
+        assert_eq!(output[14].value(), "0o17");
+
+        let float = output[0].number_literal().unwrap();
+        assert_eq!(float.radix, 10);
+        assert_eq!(float.kind, NumberKind::Float);
+        assert_eq!(float.digits, "1.5");
+        assert_eq!(float.suffix, None);
+
+        let hex = output[2].number_literal().unwrap();
+        assert_eq!(hex.radix, 16);
+        assert_eq!(hex.kind, NumberKind::Integer);
+        assert_eq!(hex.digits, "FF");
+
+        let separated = output[4].number_literal().unwrap();
+        assert_eq!(separated.digits, "1000000");
+
+        let suffixed = output[5].number_literal().unwrap();
+        assert_eq!(suffixed.digits, "10");
+        assert_eq!(suffixed.suffix, Some("u32".into()));
+
+        let exponent = output[6].number_literal().unwrap();
+        assert_eq!(exponent.kind, NumberKind::Float);
+        assert_eq!(exponent.digits, "2.5e+3");
+        assert_eq!(exponent.suffix, Some("f64".into()));
+
+        assert_eq!(output[14].number_literal().unwrap().radix, 8);
+    }
+
+    #[test]
+    fn should_lex_numbers_without_number_literals() {
+        let output = lex("1.5").unwrap();
+        assert_eq!(output.len(), 3);
+        assert!(matches!(output[0], Lexeme::Number(_, _)));
+        assert!(matches!(output[1], Lexeme::Punct(_, '.')));
+        assert!(matches!(output[2], Lexeme::Number(_, _)));
+    }
+
+    #[test]
+    fn should_recover_from_string_errors() {
+        let input = "a \"b\\q\\wc\" 1 \"open";
+        let (output, errors) = lex_recover(input);
+        assert_eq!(output.len(), 4);
+        assert_eq!(errors.len(), 3);
+        assert!(matches!(errors[0], LexError::UnexpectedEscapeInString(5, 'q')));
+        assert!(matches!(errors[1], LexError::UnexpectedEscapeInString(7, 'w')));
+        assert!(matches!(errors[2], LexError::EncounteredEndInString(13)));
+        assert!(matches!(output[0], Lexeme::Symbol(_, _)));
+        assert_eq!(output[1], Lexeme::Error(LMeta::multi(2, 9), "\"b\\q\\wc\"".into()));
+        assert!(matches!(output[2], Lexeme::Number(_, _)));
+        assert_eq!(output[3], Lexeme::Error(LMeta::multi(13, 17), "\"open".into()));
+
+        assert!(matches!(lex(input), Err(LexError::UnexpectedEscapeInString(5, 'q'))));
+    }
+
+    #[test]
+    fn should_lex_trivia() {
+        let input = "a  // line\n\t/* block /* nested */ */\"s\\t\" 1\r\n";
+        let config = LexerConfig::new().trivia(true);
+        let output = config.lex(input).unwrap();
+        assert_eq!(output.len(), 9);
+        assert_eq!(output[1], Lexeme::Trivia(LMeta::multi(1, 2), "  ".into()));
+        assert_eq!(output[2], Lexeme::Trivia(LMeta::multi(3, 9), "// line".into()));
+        assert_eq!(output[3], Lexeme::Trivia(LMeta::multi(10, 11), "\n\t".into()));
+        assert_eq!(output[4], Lexeme::Trivia(LMeta::multi(12, 35), "/* block /* nested */ */".into()));
+        assert_eq!(output[5], Lexeme::String(LMeta::multi(36, 40), "s\t".into(), "\"s\\t\"".into()));
+        assert_eq!(output[5].source(input), "\"s\\t\"");
+        assert_eq!(output[8], Lexeme::Trivia(LMeta::multi(43, 44), "\r\n".into()));
+        assert_eq!(output.iter().map(|l| l.source(input)).collect::<String>(), input);
+        assert_eq!(output.iter().map(|l| l.text()).collect::<String>(), input);
+
+        let output = config.lex("a // c\n\"s\" b").unwrap();
+        assert!(output.iter().any(|l| l.lmatch(&Lexeme::String(LMeta::new(), "s".into(), "'s'".into()))));
+    }
+
+    #[test]
+    fn should_lex_trivia_with_multi_byte_delimiters() {
+        let input = "«a\\«b« «»«";
+        let output = LexerConfig::new().string_delimiters(vec!['«']).escapes(vec![('«', '«')]).trivia(true).lex(input).unwrap();
+        assert_eq!(output.len(), 3);
+        assert_eq!(output[0], Lexeme::String(LMeta::multi(0, 7), "a«b".into(), "«a\\«b«".into()));
+        assert_eq!(output[0].end_offset(), 9);
+        assert_eq!(output[2].source(input), "«»«");
+        assert_eq!(output.iter().map(|l| l.source(input)).collect::<String>(), input);
+        assert_eq!(output.iter().map(|l| l.text()).collect::<String>(), input);
+    }
 }
\ No newline at end of file
@@ -1,3 +1,3 @@
-
 pub mod lexer;
 pub mod bracketer;
+pub mod unparser;
@@ -0,0 +1,285 @@
+
+use crate::data::{Lexeme, Bracket};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Spacing {
+    /// A space between every pair of lexemes.
+    Always,
+    /// A space only where two lexemes would otherwise run together into different lexemes with the default
+    /// lexer.  Output lexed with registered operators or number literals can lex differently, so that `1` `.` `5`
+    /// becomes one number and `-` `-` becomes a `--` operator.
+    Minimal,
+}
+
+#[derive(Debug, Clone)]
+pub struct UnparseConfig {
+    spacing : Spacing,
+    indent : String,
+    line_breaks : bool,
+    string_delimiter : char,
+    escapes : Vec<(char, char)>,
+}
+
+impl Default for UnparseConfig {
+    fn default() -> Self {
+        UnparseConfig {
+            spacing: Spacing::Always,
+            indent: "    ".into(),
+            line_breaks: false,
+            string_delimiter: '"',
+            escapes: vec![('t', '\t'), ('n', '\n'), ('r', '\r'), ('0', '\0'), ('\\', '\\'), ('"', '"')],
+        }
+    }
+}
+
+impl UnparseConfig {
+    pub fn new() -> Self {
+        UnparseConfig::default()
+    }
+    pub fn spacing(mut self, spacing : Spacing) -> Self {
+        self.spacing = spacing;
+        self
+    }
+    pub fn indent(mut self, indent : &str) -> Self {
+        self.indent = indent.to_string();
+        self
+    }
+    /// Puts the contents of curly brackets on their own indented lines and breaks lines after ';'.
+    pub fn line_breaks(mut self, enable : bool) -> Self {
+        self.line_breaks = enable;
+        self
+    }
+    pub fn string_delimiter(mut self, delimiter : char) -> Self {
+        self.string_delimiter = delimiter;
+        self
+    }
+    /// Uses the same orientation as `LexerConfig::escapes`, so the same table can be given to both.  The string
+    /// delimiter and `\\` are always escaped, so the lexer needs them in its table as well.
+    pub fn escapes(mut self, escapes : Vec<(char, char)>) -> Self {
+        self.escapes = escapes;
+        self
+    }
+
+    pub fn unparse_lexemes(&self, input : &[Lexeme]) -> String {
+        let mut writer = Writer { config: self, out: String::new(), depth: 0, line_start: true, after_trivia: false };
+        let mut flat = vec![];
+        flatten_groups(input, &mut flat);
+        for (index, l) in flat.iter().enumerate() {
+            writer.lexeme(l, flat.get(index + 1).copied());
+        }
+        writer.out
+    }
+
+    pub fn unparse_brackets(&self, input : &[Bracket]) -> String {
+        let mut flat = vec![];
+        flatten_brackets(input, &mut flat);
+        self.unparse_lexemes(&flat)
+    }
+
+    pub(crate) fn escape(&self, s : &str) -> String {
+        let mut ret = vec![self.string_delimiter];
+        for c in s.chars() {
+            match self.escapes.iter().find(|(_, value)| *value == c) {
+                Some((e, _)) => { ret.push('\\'); ret.push(*e); },
+                None if c == self.string_delimiter || c == '\\' => { ret.push('\\'); ret.push(c); },
+                None => { ret.push(c); },
+            }
+        }
+        ret.push(self.string_delimiter);
+        ret.into_iter().collect()
+    }
+}
+
+pub fn unparse_lexemes(input : &[Lexeme]) -> String {
+    UnparseConfig::default().unparse_lexemes(input)
+}
+
+pub fn unparse_brackets(input : &[Bracket]) -> String {
+    UnparseConfig::default().unparse_brackets(input)
+}
+
+fn flatten_groups<'a>(input : &'a [Lexeme], output : &mut Vec<&'a Lexeme>) {
+    for l in input {
+        match l {
+            Lexeme::Group(_, _, ls) => flatten_groups(ls, output),
+            l => output.push(l),
+        }
+    }
+}
+
+fn flatten_brackets(input : &[Bracket], output : &mut Vec<Lexeme>) {
+    use crate::matching::lexeme::*;
+
+    fn surround(open : Lexeme, xs : &[Bracket], close : Lexeme, output : &mut Vec<Lexeme>) {
+        output.push(open);
+        flatten_brackets(xs, output);
+        output.push(close);
+    }
+
+    for b in input {
+        match b {
+            Bracket::Paren(_, xs) => surround(l_paren(), xs, r_paren(), output),
+            Bracket::Angle(_, xs) => surround(l_angle(), xs, r_angle(), output),
+            Bracket::Curl(_, xs) => surround(l_curl(), xs, r_curl(), output),
+            Bracket::Square(_, xs) => surround(l_square(), xs, r_square(), output),
+            Bracket::Lex(l) => output.push(l.clone()),
+            Bracket::Error(l) => output.push(l.clone()),
+        }
+    }
+}
+
+struct Writer<'a> {
+    config : &'a UnparseConfig,
+    out : String,
+    depth : usize,
+    line_start : bool,
+    after_trivia : bool,
+}
+
+impl Writer<'_> {
+    fn lexeme(&mut self, l : &Lexeme, next : Option<&Lexeme>) {
+        let breaks = self.config.line_breaks;
+        match l {
+            Lexeme::LCurl(_) if breaks && matches!(next, Some(Lexeme::RCurl(_))) => { self.token("{"); },
+            Lexeme::LCurl(_) if breaks => {
+                self.token("{");
+                self.depth += 1;
+                self.newline();
+            },
+            Lexeme::RCurl(_) if breaks && self.out.ends_with('{') => {
+                self.out.push('}');
+                self.end_line(next);
+            },
+            Lexeme::RCurl(_) if breaks => {
+                self.depth = self.depth.saturating_sub(1);
+                self.newline();
+                self.token("}");
+                self.end_line(next);
+            },
+            Lexeme::Punct(_, ';') if breaks => {
+                self.token(";");
+                self.end_line(next);
+            },
+            Lexeme::String(_, s, _) => {
+                let s = self.config.escape(s);
+                self.token(&s);
+            },
+            Lexeme::Trivia(_, t) => {
+                self.out.push_str(t);
+                self.line_start = false;
+                self.after_trivia = true;
+            },
+            l => { self.token(&l.value()); },
+        }
+    }
+
+    fn end_line(&mut self, next : Option<&Lexeme>) {
+        match next {
+            Some(Lexeme::RParen(_) | Lexeme::RAngle(_) | Lexeme::RCurl(_) | Lexeme::RSquare(_)) => { },
+            Some(Lexeme::Punct(_, ',' | ';')) => { },
+            Some(_) => { self.newline(); },
+            None => { },
+        }
+    }
+
+    fn newline(&mut self) {
+        if !self.line_start {
+            self.out.push('\n');
+            self.line_start = true;
+        }
+    }
+
+    fn token(&mut self, text : &str) {
+        if self.line_start {
+            if !self.out.is_empty() {
+                self.out.push_str(&self.config.indent.repeat(self.depth));
+            }
+        }
+        else if !self.after_trivia && self.needs_space(text) {
+            self.out.push(' ');
+        }
+        self.out.push_str(text);
+        self.line_start = false;
+        self.after_trivia = false;
+    }
+
+    fn needs_space(&self, text : &str) -> bool {
+        fn word(c : char) -> bool { c.is_alphanumeric() || c == '_' }
+        fn symbolic(c : char) -> bool { !word(c) && !c.is_whitespace() && !"(){}[],;\"".contains(c) }
+
+        match (self.config.spacing, self.out.chars().last(), text.chars().next()) {
+            (Spacing::Always, _, _) => true,
+            (Spacing::Minimal, Some(a), Some(b)) => (word(a) && word(b)) || (symbolic(a) && symbolic(b)),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parsing::{lexer, bracketer};
+    use crate::matching::lexeme::*;
+
+    #[test]
+    fn should_unparse_lexemes() {
+        let input = vec![ symbol("x")
+                        , punct('=')
+                        , group("call", vec![symbol("f"), l_paren(), string("a\t\"b\""), punct(','), number("1"), r_paren()])
+                        , punct(';')
+                        ];
+        assert_eq!(unparse_lexemes(&input), "x = f ( \"a\\t\\\"b\\\"\" , 1 ) ;");
+
+        let config = UnparseConfig::new().spacing(Spacing::Minimal).string_delimiter('\'').escapes(vec![('\'', '\'')]);
+        assert_eq!(config.unparse_lexemes(&input), "x=f('a\t\"b\"',1);");
+    }
+
+    #[test]
+    fn should_escape_the_string_delimiter() {
+        let input = vec![string("it's \\")];
+        let output = UnparseConfig::new().string_delimiter('\'').unparse_lexemes(&input);
+        assert_eq!(output, "'it\\'s \\\\'");
+
+        let config = lexer::LexerConfig::new().string_delimiters(vec!['\'']).escapes(vec![('\'', '\''), ('\\', '\\')]);
+        let relexed = config.lex(&output).unwrap();
+        assert!(matches!(&relexed[..], [Lexeme::String(_, s, _)] if s == "it's \\"));
+    }
+
+    #[test]
+    fn should_unparse_trivia_verbatim() {
+        let input = "a  b /* c */ + 1\n";
+        let tokens = lexer::LexerConfig::new().trivia(true).lex(input).unwrap();
+        assert_eq!(unparse_lexemes(&tokens), input);
+        assert_eq!(UnparseConfig::new().spacing(Spacing::Minimal).unparse_lexemes(&tokens), input);
+    }
+
+    #[test]
+    fn should_unparse_minimal_spacing_without_fusing_lexemes() {
+        let input = vec![symbol("a"), symbol("b"), punct('/'), punct('/'), number("1"), punct('-'), operator("->"), l_angle(), punct('=')];
+        let config = UnparseConfig::new().spacing(Spacing::Minimal);
+        assert_eq!(config.unparse_lexemes(&input), "a b/ /1- -> < =");
+    }
+
+    #[test]
+    fn should_unparse_brackets_with_line_breaks() {
+        let input = "if (stuff) { a = 1; b = [1, 2]; {} } else { c = y(1, 2, 3); }";
+        let tokens = lexer::lex(input).unwrap().into_iter();
+        let brackets = bracketer::bracket(tokens).unwrap();
+        let config = UnparseConfig::new().line_breaks(true).indent("  ");
+        let output = config.unparse_brackets(&brackets);
+        let expected = "\
+if ( stuff ) {
+  a = 1 ;
+  b = [ 1 , 2 ] ;
+  {}
+}
+else {
+  c = y ( 1 , 2 , 3 ) ;
+}";
+        assert_eq!(output, expected);
+
+        let reparsed = bracketer::bracket(lexer::lex(&output).unwrap().into_iter()).unwrap();
+        assert_eq!(reparsed.len(), brackets.len());
+        assert!(reparsed.iter().zip(brackets.iter()).all(|(a, b)| a.lmatch(b)));
+    }
+}